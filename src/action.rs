@@ -0,0 +1,117 @@
+
+use std::f64;
+
+extern crate rand;
+use self::rand::Rng;
+
+
+/// The things a creature can choose to do on each tick, when it can only do one thing at a time
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Action {
+    Eat,
+    Move,
+    TurnLeft,
+    TurnRight,
+    Rest,
+    Reproduce,
+    Attack,
+}
+
+pub const ACTIONS : usize = 7;
+
+/// How much more likely an action is to be picked for each step in its output, as a power of e
+const SHARPNESS : f64 = 3.0;
+
+const ALL_ACTIONS : [Action; ACTIONS] = [ Action::Eat, Action::Move, Action::TurnLeft, Action::TurnRight, Action::Rest, Action::Reproduce, Action::Attack ];
+
+impl Action {
+    /// Returns the action at the given position in the order of the brain's action outputs
+    pub fn from_index(index: usize) -> Option<Action>
+    {
+        ALL_ACTIONS.get(index).cloned()
+    }
+}
+
+/// Makes each creature's brain choose one action on each tick, instead of always moving and eating
+#[derive(Copy, Clone, Debug)]
+pub struct Actions {
+    /// The extra cost of each action, as a multiple of the normal cost of living, in the order eat, move, turn, rest,
+    /// reproduce, and attack.  Resting can cost less than nothing, which lowers the cost of living
+    pub costs: [f64; ACTIONS - 1],
+    /// Creatures can attack the nearest creature to take some of its size
+    pub attack: bool,
+}
+
+impl Actions {
+    pub fn new() -> Actions
+    {
+        Actions {
+            costs: [ 0.1, 0.5, 0.1, -0.5, 0.5, 1.0 ],
+            attack: false,
+        }
+    }
+
+    /// Parses a description of the form eat:move:turn:rest:reproduce:attack
+    pub fn from_str(desc: &str) -> Result<Actions, String>
+    {
+        let parts : Vec<&str> = desc.split(':').collect();
+        if parts.len() != ACTIONS - 1 {
+            return Err(format!("invalid action costs {}, expected eat:move:turn:rest:reproduce:attack", desc));
+        }
+
+        let mut costs = [0.0; ACTIONS - 1];
+        for (i, part) in parts.iter().enumerate() {
+            costs[i] = part.parse::<f64>().map_err(|_| format!("invalid number {} in action costs {}", part, desc))?.max(-1.0);
+        }
+
+        Ok(Actions {
+            costs: costs,
+            attack: false,
+        })
+    }
+
+    pub fn cost(&self, action: Action) -> f64
+    {
+        match action {
+            Action::Eat => self.costs[0],
+            Action::Move => self.costs[1],
+            Action::TurnLeft | Action::TurnRight => self.costs[2],
+            Action::Rest => self.costs[3],
+            Action::Reproduce => self.costs[4],
+            Action::Attack => self.costs[5],
+        }
+    }
+
+    /// Returns true if the action can be taken, where reproducing is only allowed when the brain decides when to reproduce
+    pub fn allowed(&self, action: Action, reproduce: bool) -> bool
+    {
+        (action != Action::Reproduce || reproduce) && (action != Action::Attack || self.attack)
+    }
+
+    /// Returns whether each action can be taken, in the order of the brain's action outputs
+    pub fn allowed_mask(&self, reproduce: bool) -> Vec<bool>
+    {
+        ALL_ACTIONS.iter().map(|&action| self.allowed(action, reproduce)).collect()
+    }
+
+    /// Picks one of the allowed actions at random, where actions with stronger outputs are much more likely to be picked, so
+    /// that a creature doesn't get stuck doing the same thing forever before it has evolved to respond to its senses
+    pub fn choose(&self, outputs: &[f64], reproduce: bool) -> Action
+    {
+        let weights : Vec<(Action, f64)> = ALL_ACTIONS.iter().zip(outputs.iter())
+            .filter(|&(&action, _)| self.allowed(action, reproduce))
+            .map(|(&action, &output)| (action, (output * SHARPNESS).exp()))
+            .collect();
+
+        let total : f64 = weights.iter().map(|&(_, weight)| weight).sum();
+        let mut choice = rand::thread_rng().gen_range(0.0, total.max(f64::MIN_POSITIVE));
+        for &(action, weight) in &weights {
+            if choice < weight {
+                return action;
+            }
+            choice -= weight;
+        }
+        return Action::Rest;
+    }
+}
+
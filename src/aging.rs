@@ -0,0 +1,86 @@
+
+extern crate rand;
+use self::rand::Rng;
+
+use world::WorldTime;
+
+
+/// Makes creatures grow old, so that they cost more to keep alive, have fewer offspring, and become more likely to die
+/// the closer they get to the end of their lifespan
+#[derive(Copy, Clone, Debug)]
+pub struct Aging {
+    /// The typical lifespan in ticks
+    pub lifespan: f64,
+    /// How much the cost of living has gone up by the end of the lifespan, as a multiple of the normal cost
+    pub cost: f64,
+    /// The chance of dying on each tick at the end of the lifespan, which is lower when younger and higher when older
+    pub mortality: f64,
+    /// The fraction of the chance of reproducing that's lost by the end of the lifespan
+    pub fertility: f64,
+    /// Each creature's lifespan is scaled by its inherited longevity, at the expense of reproducing less often
+    pub heritable: bool,
+}
+
+impl Aging {
+    pub fn new() -> Aging
+    {
+        Aging {
+            lifespan: 20000.0,
+            cost: 1.0,
+            mortality: 0.001,
+            fertility: 0.5,
+            heritable: false,
+        }
+    }
+
+    /// Parses a description of the form lifespan:cost:mortality:fertility
+    pub fn from_str(desc: &str) -> Result<Aging, String>
+    {
+        let parts : Vec<&str> = desc.split(':').collect();
+        if parts.len() != 4 {
+            return Err(format!("invalid aging {}, expected lifespan:cost:mortality:fertility", desc));
+        }
+
+        let number = |s: &str| s.parse::<f64>().map_err(|_| format!("invalid number {} in aging {}", s, desc));
+        Ok(Aging {
+            lifespan: number(parts[0])?.max(1.0),
+            cost: number(parts[1])?.max(0.0),
+            mortality: number(parts[2])?.max(0.0).min(1.0),
+            fertility: number(parts[3])?.max(0.0).min(1.0),
+            heritable: false,
+        })
+    }
+
+    /// The creature's age as a fraction of its lifespan
+    fn relative_age(&self, age: WorldTime, longevity: f64) -> f64
+    {
+        let lifespan = if self.heritable { self.lifespan * longevity } else { self.lifespan };
+        age as f64 / lifespan.max(1.0)
+    }
+
+    /// The extra cost of living, as a multiple of the normal cost, which grows slowly at first and quickly near the end
+    pub fn senescence(&self, age: WorldTime, longevity: f64) -> f64
+    {
+        self.cost * self.relative_age(age, longevity).powf(2.0)
+    }
+
+    /// The chance of dying of old age on this tick
+    pub fn mortality(&self, age: WorldTime, longevity: f64) -> f64
+    {
+        (self.mortality * self.relative_age(age, longevity).powf(4.0)).min(1.0)
+    }
+
+    /// The multiplier on the chance of reproducing
+    pub fn fertility(&self, age: WorldTime, longevity: f64) -> f64
+    {
+        let fertility = (1.0 - self.fertility * self.relative_age(age, longevity)).max(0.0);
+        if self.heritable { fertility / longevity.max(0.1) } else { fertility }
+    }
+}
+
+/// The longevity of a child, which is changed slightly from its parent's
+pub fn mutate_longevity(longevity: f64) -> f64
+{
+    (longevity * (1.0 + rand::thread_rng().gen_range(-0.05, 0.05))).max(0.1).min(10.0)
+}
+
@@ -0,0 +1,429 @@
+
+use std::f64;
+use std::fs::File;
+use std::io::{ BufRead, BufReader };
+
+extern crate rand;
+use self::rand::Rng;
+
+use world::{ WorldTime, Boundary };
+
+
+/// Decides how fast food grows on each tile over time
+pub trait Climate {
+    /// Moves the climate on to the given time
+    fn update(&mut self, time: WorldTime);
+
+    /// The growth multiplier for a tile, which is usually between 0.0 and 1.0
+    fn growth(&self, col: usize, row: usize) -> f64;
+
+    /// A single number describing the current state of the climate as a whole
+    fn season(&self) -> f64;
+
+    /// The row where growth is strongest, if growth moves across the terrain in a band
+    fn band(&self) -> Option<usize>
+    {
+        None
+    }
+
+    /// A short description of the current conditions, such as any weather events
+    fn describe(&self) -> String;
+}
+
+
+#[derive(Clone, Debug)]
+pub enum ClimateKind {
+    /// The original model, a window of growth which moves up the terrain and wraps around
+    Band,
+    /// The whole terrain grows at once for half of each year, and not at all for the other half
+    Global,
+    /// A fixed gradient of growth from the top of the terrain to the bottom
+    Gradient,
+    /// Constant growth, interrupted by random droughts and blooms over parts of the terrain
+    Weather,
+    /// Growth is set over time by a script
+    Schedule(Vec<Keyframe>),
+}
+
+impl ClimateKind {
+    pub fn from_str(name: &str) -> Option<ClimateKind>
+    {
+        match name {
+            "band" => Some(ClimateKind::Band),
+            "global" => Some(ClimateKind::Global),
+            "gradient" => Some(ClimateKind::Gradient),
+            "weather" => Some(ClimateKind::Weather),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ClimateConfig {
+    pub kind: ClimateKind,
+    /// The length of a year in ticks, for the climates that repeat
+    pub period: f64,
+}
+
+impl ClimateConfig {
+    pub fn new() -> ClimateConfig
+    {
+        ClimateConfig {
+            kind: ClimateKind::Band,
+            period: 10000.0,
+        }
+    }
+
+    pub fn build(&self, size: [usize; 2], boundary: Boundary) -> Box<dyn Climate>
+    {
+        match self.kind {
+            ClimateKind::Band => Box::new(MovingBand::new(self.period, size)),
+            ClimateKind::Global => Box::new(GlobalSeasons::new(self.period)),
+            ClimateKind::Gradient => Box::new(Gradient::new(size)),
+            ClimateKind::Weather => Box::new(Weather::new(size, boundary)),
+            ClimateKind::Schedule(ref keyframes) => Box::new(Schedule::new(keyframes.clone())),
+        }
+    }
+}
+
+
+pub struct MovingBand {
+    period: f64,
+    size: [usize; 2],
+    season: f64,
+}
+
+impl MovingBand {
+    pub fn new(period: f64, size: [usize; 2]) -> MovingBand
+    {
+        MovingBand {
+            period: period.max(1.0),
+            size: size,
+            season: 0.0,
+        }
+    }
+}
+
+impl Climate for MovingBand {
+    fn update(&mut self, time: WorldTime)
+    {
+        self.season = (2.0 * f64::consts::PI * (time as f64 % self.period) / self.period).sin();
+    }
+
+    fn growth(&self, _col: usize, row: usize) -> f64
+    {
+        (2.0 * f64::consts::PI * ((row as f64 / self.size[1] as f64 + self.season) % 1.0) + (f64::consts::PI / 2.0)).sin().max(0.0)
+    }
+
+    fn season(&self) -> f64
+    {
+        self.season
+    }
+
+    fn band(&self) -> Option<usize>
+    {
+        // growth peaks where the row plus the season is a whole number
+        Some(((1.0 - (self.season % 1.0 + 1.0) % 1.0) * self.size[1] as f64) as usize % self.size[1])
+    }
+
+    fn describe(&self) -> String
+    {
+        String::from("band")
+    }
+}
+
+
+pub struct GlobalSeasons {
+    period: f64,
+    season: f64,
+}
+
+impl GlobalSeasons {
+    pub fn new(period: f64) -> GlobalSeasons
+    {
+        GlobalSeasons {
+            period: period.max(1.0),
+            season: 0.0,
+        }
+    }
+}
+
+impl Climate for GlobalSeasons {
+    fn update(&mut self, time: WorldTime)
+    {
+        self.season = (2.0 * f64::consts::PI * time as f64 / self.period).sin().max(0.0);
+    }
+
+    fn growth(&self, _col: usize, _row: usize) -> f64
+    {
+        self.season
+    }
+
+    fn season(&self) -> f64
+    {
+        self.season
+    }
+
+    fn describe(&self) -> String
+    {
+        String::from(if self.season > 0.0 { "summer" } else { "winter" })
+    }
+}
+
+
+pub struct Gradient {
+    size: [usize; 2],
+}
+
+impl Gradient {
+    pub fn new(size: [usize; 2]) -> Gradient
+    {
+        Gradient {
+            size: size,
+        }
+    }
+}
+
+impl Climate for Gradient {
+    fn update(&mut self, _time: WorldTime)
+    {
+    }
+
+    fn growth(&self, _col: usize, row: usize) -> f64
+    {
+        1.0 - row as f64 / (self.size[1] as f64 - 1.0).max(1.0)
+    }
+
+    fn season(&self) -> f64
+    {
+        0.5
+    }
+
+    fn describe(&self) -> String
+    {
+        String::from("gradient")
+    }
+}
+
+
+const EVENT_CHANCE : f64 = 0.002;
+const MAX_EVENTS : usize = 4;
+const DROUGHT : f64 = 0.05;
+const BLOOM : f64 = 3.0;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum EventKind {
+    Drought,
+    Bloom,
+}
+
+#[derive(Clone, Debug)]
+struct WeatherEvent {
+    kind: EventKind,
+    centre: [f64; 2],
+    radius: f64,
+    until: WorldTime,
+}
+
+impl WeatherEvent {
+    fn factor(&self) -> f64
+    {
+        match self.kind {
+            EventKind::Drought => DROUGHT,
+            EventKind::Bloom => BLOOM,
+        }
+    }
+}
+
+pub struct Weather {
+    size: [usize; 2],
+    boundary: Boundary,
+    events: Vec<WeatherEvent>,
+    season: f64,
+}
+
+impl Weather {
+    pub fn new(size: [usize; 2], boundary: Boundary) -> Weather
+    {
+        Weather {
+            size: size,
+            boundary: boundary,
+            events: vec!(),
+            season: 1.0,
+        }
+    }
+
+    fn covers(&self, event: &WeatherEvent, col: usize, row: usize) -> bool
+    {
+        self.boundary.distance([ col as f64, row as f64 ], event.centre, self.size) <= event.radius
+    }
+}
+
+impl Climate for Weather {
+    fn update(&mut self, time: WorldTime)
+    {
+        self.events.retain(|event| event.until > time);
+
+        if self.events.len() < MAX_EVENTS && rand::thread_rng().gen_range(0.0, 1.0) < EVENT_CHANCE {
+            let mut rng = rand::thread_rng();
+            self.events.push(WeatherEvent {
+                kind: if rng.gen_range(0.0, 1.0) < 0.5 { EventKind::Drought } else { EventKind::Bloom },
+                centre: [ rng.gen_range(0.0, self.size[0] as f64), rng.gen_range(0.0, self.size[1] as f64) ],
+                radius: rng.gen_range(10.0, 40.0),
+                until: time + rng.gen_range(500, 3000),
+            });
+        }
+
+        // the average growth over the whole terrain, ignoring any overlap between events
+        let area = (self.size[0] * self.size[1]) as f64;
+        self.season = 1.0 + self.events.iter().map(|event| (event.factor() - 1.0) * (f64::consts::PI * event.radius * event.radius / area).min(1.0)).sum::<f64>();
+    }
+
+    fn growth(&self, col: usize, row: usize) -> f64
+    {
+        let mut growth = 1.0;
+        for event in &self.events {
+            if self.covers(event, col, row) {
+                growth *= event.factor();
+            }
+        }
+        // overlapping blooms don't make growth any faster
+        return growth.min(BLOOM);
+    }
+
+    fn season(&self) -> f64
+    {
+        self.season
+    }
+
+    fn describe(&self) -> String
+    {
+        if self.events.len() <= 0 {
+            return String::from("calm");
+        }
+        self.events.iter().map(|event| format!("{:?}", event.kind).to_lowercase()).collect::<Vec<String>>().join(";")
+    }
+}
+
+
+/// A point in a climate schedule, where the growth over the whole terrain is set at the given time
+#[derive(Clone, Debug)]
+pub struct Keyframe {
+    pub time: WorldTime,
+    pub growth: f64,
+    pub label: String,
+}
+
+/// Reads a schedule file, where each line has a time, a growth multiplier, and an optional label.  Blank lines and lines
+/// starting with # are ignored
+pub fn load_schedule(path: &str) -> Result<Vec<Keyframe>, String>
+{
+    let file = File::open(path).map_err(|err| format!("error opening climate schedule {}: {}", path, err))?;
+
+    let mut keyframes : Vec<Keyframe> = vec!();
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|err| format!("error reading climate schedule {}: {}", path, err))?;
+        let line = line.trim();
+        if line.len() <= 0 || line.starts_with('#') {
+            continue;
+        }
+
+        let invalid = || format!("invalid line {} in climate schedule {}, expected: time growth [label]", i + 1, path);
+        let parts : Vec<&str> = line.split_whitespace().collect();
+        if parts.len() < 2 {
+            return Err(invalid());
+        }
+        let keyframe = Keyframe {
+            time: parts[0].parse().map_err(|_| invalid())?,
+            growth: parts[1].parse().map_err(|_| invalid())?,
+            label: parts[2..].join(" "),
+        };
+
+        if keyframes.last().map_or(false, |last| last.time >= keyframe.time) {
+            return Err(format!("times must increase in climate schedule {}, at line {}", path, i + 1));
+        }
+        keyframes.push(keyframe);
+    }
+
+    if keyframes.len() <= 0 {
+        return Err(format!("climate schedule {} is empty", path));
+    }
+    return Ok(keyframes);
+}
+
+/// Growth over the whole terrain changes smoothly between the keyframes of a script, which repeats after the last keyframe
+pub struct Schedule {
+    keyframes: Vec<Keyframe>,
+    growth: f64,
+    label: String,
+}
+
+impl Schedule {
+    pub fn new(keyframes: Vec<Keyframe>) -> Schedule
+    {
+        Schedule {
+            keyframes: keyframes,
+            growth: 0.0,
+            label: String::new(),
+        }
+    }
+}
+
+impl Climate for Schedule {
+    fn update(&mut self, time: WorldTime)
+    {
+        let length = self.keyframes.last().map_or(0, |last| last.time);
+        let time = if length > 0 { time % length } else { time };
+
+        match self.keyframes.iter().position(|keyframe| keyframe.time > time) {
+            Some(next) if next > 0 => {
+                let (a, b) = (&self.keyframes[next - 1], &self.keyframes[next]);
+                let t = (time - a.time) as f64 / (b.time - a.time) as f64;
+                self.growth = a.growth + t * (b.growth - a.growth);
+                self.label = a.label.clone();
+            },
+            Some(next) => {
+                self.growth = self.keyframes[next].growth;
+                self.label = self.keyframes[next].label.clone();
+            },
+            None => {
+                let last = &self.keyframes[self.keyframes.len() - 1];
+                self.growth = last.growth;
+                self.label = last.label.clone();
+            },
+        }
+    }
+
+    fn growth(&self, _col: usize, _row: usize) -> f64
+    {
+        self.growth
+    }
+
+    fn season(&self) -> f64
+    {
+        self.growth
+    }
+
+    fn describe(&self) -> String
+    {
+        if self.label.len() > 0 { self.label.clone() } else { String::from("schedule") }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn weather_only_spills_over_the_edges_on_a_torus()
+    {
+        let event = WeatherEvent { kind: EventKind::Drought, centre: [ 1.0, 50.0 ], radius: 5.0, until: 100 };
+        let torus = Weather::new([ 200, 100 ], Boundary::Torus);
+        let walled = Weather::new([ 200, 100 ], Boundary::Wall);
+
+        assert!(torus.covers(&event, 3, 50) && walled.covers(&event, 3, 50));
+        assert!(torus.covers(&event, 198, 50));
+        assert!(!walled.covers(&event, 198, 50));
+    }
+}
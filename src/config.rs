@@ -0,0 +1,396 @@
+
+use std::env;
+
+use world::{ WorldTime, CreatureID, Rules, Boundary, Reproduction };
+use stats::StatsFormat;
+use dump::RankBy;
+use format::Format;
+use library::Query;
+use seed::{ Seed, SeedSource, DEFAULT_POPULATION };
+use generator::{ Generator, TerrainConfig };
+use resource::{ Resource, MAX_RESOURCES };
+use climate::{ self, ClimateKind };
+use vegetation::Vegetation;
+use scent::ScentConfig;
+use aging::Aging;
+use action::Actions;
+use plasticity::Plasticity;
+use population::PopulationConfig;
+use evolution::{ EvolutionConfig, Episode, Fitness };
+use environment::EnvironmentConfig;
+
+
+pub struct Config {
+    pub stats_file: Option<String>,
+    pub stats_format: StatsFormat,
+    pub stats_interval: WorldTime,
+    pub species_threshold: f64,
+    pub rank_by: RankBy,
+    pub library: String,
+    pub format: Format,
+    pub list_creatures: bool,
+    pub query: Query,
+    pub seeds: Vec<Seed>,
+    pub terrain: TerrainConfig,
+    pub map: Option<String>,
+    pub rules: Rules,
+    pub population: PopulationConfig,
+    pub hall_of_fame: Option<String>,
+    pub hall_size: usize,
+    pub evolve: bool,
+    pub evolution: EvolutionConfig,
+    pub serve: bool,
+    pub environment: EnvironmentConfig,
+}
+
+impl Config {
+    pub fn new() -> Config
+    {
+        Config {
+            stats_file: None,
+            stats_format: StatsFormat::Csv,
+            stats_interval: 100,
+            species_threshold: 5.0,
+            rank_by: RankBy::EatenRate,
+            library: String::from("creatures"),
+            format: Format::Json,
+            list_creatures: false,
+            query: Query::default(),
+            seeds: vec!(),
+            terrain: TerrainConfig::new(),
+            map: None,
+            rules: Rules::new(),
+            population: PopulationConfig::new(),
+            hall_of_fame: None,
+            hall_size: 10,
+            evolve: false,
+            evolution: EvolutionConfig::new(),
+            serve: false,
+            environment: EnvironmentConfig::new(),
+        }
+    }
+
+    pub fn from_args() -> Result<Config, String>
+    {
+        let mut config = Config::new();
+        let mut args = env::args().skip(1);
+        let mut resources = vec!();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--stats" => {
+                    let filename = try_next(&mut args, &arg)?;
+                    config.stats_format = StatsFormat::from_filename(&filename);
+                    config.stats_file = Some(filename);
+                },
+                "--stats-format" => {
+                    config.stats_format = match try_next(&mut args, &arg)?.as_str() {
+                        "csv" => StatsFormat::Csv,
+                        "json" | "jsonl" => StatsFormat::JsonLines,
+                        format => return Err(format!("unknown stats format: {}", format)),
+                    };
+                },
+                "--stats-every" => {
+                    config.stats_interval = try_parse(&try_next(&mut args, &arg)?, &arg)?;
+                    if config.stats_interval == 0 {
+                        return Err(format!("{} must be greater than 0", arg));
+                    }
+                },
+                "--species-threshold" => {
+                    config.species_threshold = try_parse(&try_next(&mut args, &arg)?, &arg)?;
+                },
+                "--rank-by" => {
+                    let name = try_next(&mut args, &arg)?;
+                    config.rank_by = match RankBy::from_str(&name) {
+                        Some(rank_by) => rank_by,
+                        None => return Err(format!("unknown ranking metric: {}", name)),
+                    };
+                },
+                "--library" => {
+                    config.library = try_next(&mut args, &arg)?;
+                },
+                "--format" => {
+                    let name = try_next(&mut args, &arg)?;
+                    config.format = match Format::from_str(&name) {
+                        Some(format) => format,
+                        None => return Err(format!("unknown file format: {}", name)),
+                    };
+                },
+                "--evolve" => {
+                    config.evolve = true;
+                },
+                "--generations" => {
+                    config.evolution.generations = try_parse(&try_next(&mut args, &arg)?, &arg)?;
+                },
+                "--ga-population" => {
+                    config.evolution.population = try_parse(&try_next(&mut args, &arg)?, &arg)?;
+                    if config.evolution.population == 0 {
+                        return Err(format!("{} must be greater than 0", arg));
+                    }
+                },
+                "--episode-ticks" => {
+                    config.evolution.ticks = try_parse(&try_next(&mut args, &arg)?, &arg)?;
+                },
+                "--episode" => {
+                    let name = try_next(&mut args, &arg)?;
+                    config.evolution.episode = match Episode::from_str(&name) {
+                        Some(episode) => episode,
+                        None => return Err(format!("unknown episode type: {}", name)),
+                    };
+                },
+                "--fitness" => {
+                    let name = try_next(&mut args, &arg)?;
+                    config.evolution.fitness = match Fitness::from_str(&name) {
+                        Some(fitness) => fitness,
+                        None => return Err(format!("unknown fitness: {}", name)),
+                    };
+                },
+                "--tournament" => {
+                    config.evolution.tournament = try_parse(&try_next(&mut args, &arg)?, &arg)?;
+                },
+                "--elitism" => {
+                    config.evolution.elitism = try_parse(&try_next(&mut args, &arg)?, &arg)?;
+                },
+                "--checkpoint-dir" => {
+                    config.evolution.dir = try_next(&mut args, &arg)?;
+                },
+                "--checkpoint-every" => {
+                    config.evolution.checkpoint_interval = try_parse(&try_next(&mut args, &arg)?, &arg)?;
+                    if config.evolution.checkpoint_interval == 0 {
+                        return Err(format!("{} must be greater than 0", arg));
+                    }
+                },
+                "--resume" => {
+                    config.evolution.resume = true;
+                },
+                "--serve" => {
+                    config.serve = true;
+                    config.environment.port = try_parse(&try_next(&mut args, &arg)?, &arg)?;
+                },
+                "--agents" => {
+                    config.environment.agents = try_parse(&try_next(&mut args, &arg)?, &arg)?;
+                    if config.environment.agents == 0 {
+                        return Err(format!("{} must be greater than 0", arg));
+                    }
+                },
+                "--background" => {
+                    config.environment.background = true;
+                },
+                "--hall-of-fame" => {
+                    config.hall_of_fame = Some(try_next(&mut args, &arg)?);
+                },
+                "--hall-size" => {
+                    config.hall_size = try_parse(&try_next(&mut args, &arg)?, &arg)?;
+                    if config.hall_size == 0 {
+                        return Err(format!("{} must be greater than 0", arg));
+                    }
+                },
+                "--list-creatures" => {
+                    config.list_creatures = true;
+                },
+                "--run" => {
+                    config.query.run = Some(try_next(&mut args, &arg)?);
+                },
+                "--ancestor" => {
+                    config.query.ancestor = Some(try_parse::<CreatureID>(&try_next(&mut args, &arg)?, &arg)?);
+                },
+                "--min-spawns" => {
+                    config.query.min_spawns = Some(try_parse(&try_next(&mut args, &arg)?, &arg)?);
+                },
+                "--load" => {
+                    config.seeds.push(Seed::new(SeedSource::File(try_next(&mut args, &arg)?)));
+                },
+                "--load-dir" => {
+                    config.seeds.push(Seed::new(SeedSource::Directory(try_next(&mut args, &arg)?)));
+                },
+                "--random" => {
+                    config.seeds.push(Seed::new(SeedSource::Random(try_parse(&try_next(&mut args, &arg)?, &arg)?)));
+                },
+                "--mutate" => {
+                    let path = try_next(&mut args, &arg)?;
+                    let count = try_parse(&try_next(&mut args, &arg)?, &arg)?;
+                    config.seeds.push(Seed::new(SeedSource::Mutated(path, count)));
+                },
+                "--seed-colour" => {
+                    let colour = try_parse(&try_next(&mut args, &arg)?, &arg)?;
+                    last_seed(&mut config.seeds, &arg)?.colour = Some(colour);
+                },
+                "--seed-ancestor" => {
+                    let ancestor = try_parse(&try_next(&mut args, &arg)?, &arg)?;
+                    last_seed(&mut config.seeds, &arg)?.ancestor = Some(ancestor);
+                },
+                "--terrain" => {
+                    let name = try_next(&mut args, &arg)?;
+                    config.terrain.generator = match Generator::from_str(&name) {
+                        Some(generator) => generator,
+                        None => return Err(format!("unknown terrain generator: {}", name)),
+                    };
+                },
+                "--terrain-seed" => {
+                    config.terrain.seed = Some(try_parse(&try_next(&mut args, &arg)?, &arg)?);
+                },
+                "--terrain-scale" => {
+                    config.terrain.scale = try_parse(&try_next(&mut args, &arg)?, &arg)?;
+                },
+                "--resource" => {
+                    resources.push(Resource::from_str(&try_next(&mut args, &arg)?)?);
+                    if resources.len() > MAX_RESOURCES {
+                        return Err(format!("no more than {} resources can be used", MAX_RESOURCES));
+                    }
+                },
+                "--boundary" => {
+                    let name = try_next(&mut args, &arg)?;
+                    config.terrain.boundary = match Boundary::from_str(&name) {
+                        Some(boundary) => boundary,
+                        None => return Err(format!("unknown boundary: {}", name)),
+                    };
+                },
+                "--climate" => {
+                    let name = try_next(&mut args, &arg)?;
+                    config.terrain.climate.kind = match ClimateKind::from_str(&name) {
+                        Some(kind) => kind,
+                        None => return Err(format!("unknown climate: {}", name)),
+                    };
+                },
+                "--climate-period" => {
+                    config.terrain.climate.period = try_parse(&try_next(&mut args, &arg)?, &arg)?;
+                },
+                "--climate-schedule" => {
+                    config.terrain.climate.kind = ClimateKind::Schedule(climate::load_schedule(&try_next(&mut args, &arg)?)?);
+                },
+                "--growth-every" => {
+                    config.terrain.growth_interval = try_parse(&try_next(&mut args, &arg)?, &arg)?;
+                    if config.terrain.growth_interval == 0 {
+                        return Err(format!("{} must be greater than 0", arg));
+                    }
+                },
+                "--vegetation" => {
+                    config.terrain.vegetation = Some(Vegetation::new());
+                },
+                "--vegetation-params" => {
+                    config.terrain.vegetation = Some(Vegetation::from_str(&try_next(&mut args, &arg)?)?);
+                },
+                "--scent" => {
+                    config.terrain.scent = Some(ScentConfig::new());
+                },
+                "--scent-params" => {
+                    config.terrain.scent = Some(ScentConfig::from_str(&try_next(&mut args, &arg)?)?);
+                },
+                "--map" => {
+                    config.map = Some(try_next(&mut args, &arg)?);
+                },
+                "--metabolism" => {
+                    config.rules.metabolism = true;
+                },
+                "--signals" => {
+                    config.rules.signals = true;
+                },
+                "--reproduction" => {
+                    let name = try_next(&mut args, &arg)?;
+                    config.rules.reproduction = match Reproduction::from_str(&name) {
+                        Some(reproduction) => reproduction,
+                        None => return Err(format!("unknown reproduction mode: {}", name)),
+                    };
+                },
+                "--min-population" => {
+                    config.population.minimum = Some(try_parse(&try_next(&mut args, &arg)?, &arg)?);
+                },
+                "--max-population" => {
+                    config.population.maximum = Some(try_parse(&try_next(&mut args, &arg)?, &arg)?);
+                },
+                "--reseed-from" => {
+                    config.population.reseed = Some(try_next(&mut args, &arg)?);
+                },
+                "--immigrate" => {
+                    let path = try_next(&mut args, &arg)?;
+                    let count = try_parse(&try_next(&mut args, &arg)?, &arg)?;
+                    let interval = try_parse(&try_next(&mut args, &arg)?, &arg)?;
+                    if interval == 0 {
+                        return Err(format!("{} interval must be greater than 0", arg));
+                    }
+                    config.population.immigration = Some((path, count, interval));
+                },
+                "--novelty-bonus" => {
+                    config.rules.novelty = Some(try_parse(&try_next(&mut args, &arg)?, &arg)?);
+                },
+                "--actions" => {
+                    config.rules.actions = Some(config.rules.actions.unwrap_or(Actions::new()));
+                },
+                "--action-costs" => {
+                    let attack = config.rules.actions.map_or(false, |actions| actions.attack);
+                    let mut actions = Actions::from_str(&try_next(&mut args, &arg)?)?;
+                    actions.attack = attack;
+                    config.rules.actions = Some(actions);
+                },
+                "--attack" => {
+                    let mut actions = config.rules.actions.unwrap_or(Actions::new());
+                    actions.attack = true;
+                    config.rules.actions = Some(actions);
+                },
+                "--plasticity" => {
+                    let name = try_next(&mut args, &arg)?;
+                    config.rules.plasticity = match Plasticity::from_str(&name) {
+                        Some(plasticity) => Some(plasticity),
+                        None => return Err(format!("unknown plasticity: {}", name)),
+                    };
+                },
+                "--aging" => {
+                    config.rules.aging = Some(config.rules.aging.unwrap_or(Aging::new()));
+                },
+                "--aging-params" => {
+                    let heritable = config.rules.aging.map_or(false, |aging| aging.heritable);
+                    let mut aging = Aging::from_str(&try_next(&mut args, &arg)?)?;
+                    aging.heritable = heritable;
+                    config.rules.aging = Some(aging);
+                },
+                "--heritable-lifespan" => {
+                    let mut aging = config.rules.aging.unwrap_or(Aging::new());
+                    aging.heritable = true;
+                    config.rules.aging = Some(aging);
+                },
+                _ => return Err(format!("unknown argument: {}", arg)),
+            }
+        }
+
+        if resources.len() > 0 {
+            config.terrain.resources = resources;
+        }
+
+        if let (Some(minimum), Some(maximum)) = (config.population.minimum, config.population.maximum) {
+            if minimum > maximum {
+                return Err(format!("--min-population can't be more than --max-population"));
+            }
+        }
+
+        if config.seeds.len() <= 0 {
+            config.seeds.push(Seed::new(SeedSource::Random(DEFAULT_POPULATION)));
+        }
+
+        return Ok(config);
+    }
+}
+
+fn last_seed<'a>(seeds: &'a mut Vec<Seed>, arg: &str) -> Result<&'a mut Seed, String>
+{
+    match seeds.last_mut() {
+        Some(seed) => Ok(seed),
+        None => Err(format!("{} must come after a --load, --load-dir, --random or --mutate argument", arg)),
+    }
+}
+
+fn try_next<I: Iterator<Item=String>>(args: &mut I, arg: &str) -> Result<String, String>
+{
+    match args.next() {
+        Some(value) => Ok(value),
+        None => Err(format!("expected a value after {}", arg)),
+    }
+}
+
+fn try_parse<T: ::std::str::FromStr>(value: &str, arg: &str) -> Result<T, String>
+{
+    match value.parse() {
+        Ok(value) => Ok(value),
+        Err(_) => Err(format!("invalid value for {}: {}", arg, value)),
+    }
+}
+
@@ -0,0 +1,106 @@
+
+use world::*;
+
+
+/// Genetic diversity of the living population, measured on the brain weights
+pub struct Diversity {
+    /// The time the diversity was measured at
+    pub time: WorldTime,
+    pub mean_distance: f64,
+    pub layer_variance: Vec<f64>,
+    pub species: usize,
+    pub ancestor_distances: Vec<(CreatureID, f64)>,
+}
+
+impl Diversity {
+    pub fn measure(world: &World, threshold: f64) -> Diversity
+    {
+        let params : Vec<Vec<f64>> = world.creatures.iter().map(|creature| creature.brain.params()).collect();
+
+        let mut ancestor_distances = vec!();
+        for creature in &world.creatures {
+            if let Some(founder) = world.founders.get(&creature.ancestor) {
+                ancestor_distances.push((creature.id, distance(&creature.brain.params(), &founder.params())));
+            }
+        }
+
+        Diversity {
+            time: world.time,
+            mean_distance: mean_distance(&params),
+            layer_variance: layer_variance(world),
+            species: count_species(&params, threshold),
+            ancestor_distances: ancestor_distances,
+        }
+    }
+
+    pub fn mean_ancestor_distance(&self) -> f64
+    {
+        if self.ancestor_distances.len() <= 0 {
+            return 0.0;
+        }
+        return self.ancestor_distances.iter().map(|&(_, d)| d).sum::<f64>() / self.ancestor_distances.len() as f64;
+    }
+
+    pub fn ancestor_distance(&self, id: CreatureID) -> Option<f64>
+    {
+        self.ancestor_distances.iter().find(|&&(cid, _)| cid == id).map(|&(_, d)| d)
+    }
+}
+
+/// Euclidean distance between two flattened brains
+pub fn distance(a: &Vec<f64>, b: &Vec<f64>) -> f64
+{
+    a.iter().zip(b.iter()).map(|(x, y)| (x - y).powf(2.0)).sum::<f64>().sqrt()
+}
+
+fn mean_distance(params: &Vec<Vec<f64>>) -> f64
+{
+    let mut sum = 0.0;
+    let mut pairs = 0;
+    for i in 0..params.len() {
+        for j in (i + 1)..params.len() {
+            sum += distance(&params[i], &params[j]);
+            pairs += 1;
+        }
+    }
+
+    if pairs <= 0 {
+        return 0.0;
+    }
+    return sum / pairs as f64;
+}
+
+/// The variance of each weight across the population, averaged over all the weights in a layer
+fn layer_variance(world: &World) -> Vec<f64>
+{
+    let layers : Vec<Vec<Vec<f64>>> = world.creatures.iter().map(|creature| creature.brain.layer_params()).collect();
+    if layers.len() <= 0 {
+        return vec!();
+    }
+
+    let n = layers.len() as f64;
+    let mut variances = vec!();
+    for l in 0..layers[0].len() {
+        let width = layers[0][l].len();
+        let mut total = 0.0;
+        for p in 0..width {
+            let mean = layers.iter().map(|layer| layer[l][p]).sum::<f64>() / n;
+            total += layers.iter().map(|layer| (layer[l][p] - mean).powf(2.0)).sum::<f64>() / n;
+        }
+        variances.push(if width > 0 { total / width as f64 } else { 0.0 });
+    }
+    return variances;
+}
+
+/// Greedily groups brains into species, where each member is within the threshold distance of the species' first member
+fn count_species(params: &Vec<Vec<f64>>, threshold: f64) -> usize
+{
+    let mut representatives : Vec<&Vec<f64>> = vec!();
+    for brain in params {
+        if !representatives.iter().any(|rep| distance(rep, brain) <= threshold) {
+            representatives.push(brain);
+        }
+    }
+    return representatives.len();
+}
+
@@ -0,0 +1,124 @@
+
+use std::fs::{ self, File };
+use std::io::{ self, Write };
+use std::path::Path;
+
+use world::*;
+use format::{ self, Format };
+use stats;
+
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum RankBy {
+    Age,
+    Spawns,
+    EatenRate,
+    Descendants,
+}
+
+impl RankBy {
+    pub fn from_str(name: &str) -> Option<RankBy>
+    {
+        match name {
+            "age" => Some(RankBy::Age),
+            "spawns" => Some(RankBy::Spawns),
+            "eaten" => Some(RankBy::EatenRate),
+            "descendants" => Some(RankBy::Descendants),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str
+    {
+        match *self {
+            RankBy::Age => "age",
+            RankBy::Spawns => "spawns",
+            RankBy::EatenRate => "eaten",
+            RankBy::Descendants => "descendants",
+        }
+    }
+
+    pub fn score(&self, world: &World, creature: &Creature) -> f64
+    {
+        let age = (world.time - creature.birthday).max(1);
+        match *self {
+            RankBy::Age => age as f64,
+            RankBy::Spawns => creature.spawns as f64,
+            RankBy::EatenRate => creature.eaten / age as f64,
+            RankBy::Descendants => world.lineage.descendants(creature.id) as f64,
+        }
+    }
+}
+
+
+/// Writes every living creature into a new directory, along with an index.csv file that summarises the ranking.  In JSON,
+/// each creature is written to its own file named by its rank, and in the binary formats, all the creatures are written in
+/// ranked order to a single archive file, which is named so that it isn't mistaken for a single saved creature.  Returns
+/// the directory that was written to
+pub fn dump_ranked(world: &World, basedir: &str, rank_by: RankBy, format: Format) -> Result<String, io::Error>
+{
+    let dir = format!("{}/{}-{}", basedir, world.time, rank_by.name());
+    fs::create_dir_all(&dir)?;
+
+    let mut ranked : Vec<(f64, &Creature)> = world.creatures.iter().map(|creature| (rank_by.score(world, creature), creature)).collect();
+    ranked.sort_by(|a, b| stats::highest_first(a.0, b.0));
+
+    let archive = format::archive_name("creatures", format);
+    if format != Format::Json {
+        let creatures : Vec<&Creature> = ranked.iter().map(|&(_, creature)| creature).collect();
+        format::write(&Path::new(&dir).join(&archive), &creatures)?;
+    }
+
+    let mut index = File::create(Path::new(&dir).join("index.csv"))?;
+    writeln!(index, "rank,id,parent,ancestor,{},age,spawns,eaten_rate,descendants,size,file", rank_by.name())?;
+
+    for (i, &(score, creature)) in ranked.iter().enumerate() {
+        let filename = if format == Format::Json {
+            let filename = format!("{:04}-{}.json", i + 1, creature.id);
+            format::write(&Path::new(&dir).join(&filename), creature)?;
+            filename
+        } else {
+            archive.clone()
+        };
+
+        let age = (world.time - creature.birthday).max(1);
+        writeln!(index, "{},{},{},{},{},{},{},{},{},{},{}", i + 1, creature.id, creature.parent, creature.ancestor, score, age, creature.spawns, creature.eaten / age as f64, world.lineage.descendants(creature.id), creature.size, filename)?;
+    }
+
+    return Ok(dir);
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::process;
+    use generator::TerrainConfig;
+    use library;
+    use seed;
+    use super::*;
+
+    #[test]
+    fn binary_dumps_load_back()
+    {
+        with_terrain(TerrainConfig::new(), |terrain| {
+            let creatures = (0..5).map(|_| Creature::generate(&terrain, 1, 0.5, -1, None)).collect();
+            let world = World::from_creatures(creatures, terrain, Rules::new());
+
+            for &format in &[ Format::Bincode, Format::MessagePack ] {
+                let basedir = env::temp_dir().join(format!("dump-test-{}-{}", process::id(), format.extension()));
+                let dir = dump_ranked(&world, &basedir.to_string_lossy(), RankBy::Age, format).unwrap();
+
+                // the archive isn't mistaken for a single saved creature
+                assert_eq!(library::find_creature_files(Path::new(&dir)).unwrap().len(), 0);
+
+                let mut errors = vec!();
+                let brains = seed::load_brains(&dir, &mut errors);
+                assert_eq!(errors.len(), 0);
+                assert_eq!(brains.map(|brains| brains.len()), Some(5));
+
+                fs::remove_dir_all(&basedir).unwrap();
+            }
+        });
+    }
+}
@@ -0,0 +1,287 @@
+
+use std::collections::HashMap;
+use std::io::{ self, BufRead, BufReader, Write };
+use std::net::{ TcpListener, TcpStream };
+use std::path::Path;
+
+use serde_json;
+
+use world::*;
+use seed::{ self, Seed };
+use generator::TerrainConfig;
+use mapfile;
+use action::{ Action, ACTIONS };
+use signal::{ self, SIGNAL_CHANNELS };
+
+
+pub struct EnvironmentConfig {
+    /// The number of creatures controlled from outside the world
+    pub agents: usize,
+    /// Add the seeded creatures to each episode, driven by their own brains, so the agents aren't alone
+    pub background: bool,
+    /// The port to listen on for clients, on the local machine only
+    pub port: u16,
+}
+
+impl EnvironmentConfig {
+    pub fn new() -> EnvironmentConfig
+    {
+        EnvironmentConfig {
+            agents: 1,
+            background: false,
+            port: 5757,
+        }
+    }
+}
+
+
+/// What happened to an agent over one step
+pub struct Transition {
+    /// The sensor values the agent's brain would be given on the next tick
+    pub observation: Vec<f64>,
+    /// The food eaten during the step, plus the change in size
+    pub reward: f64,
+    /// The agent has died, and the rest of its steps are ignored
+    pub done: bool,
+}
+
+struct Agent {
+    id: CreatureID,
+    eaten: f64,
+    size: f64,
+    done: bool,
+}
+
+/// An interface for training controllers with reinforcement learning, in the style of a gym environment.  Each episode
+/// starts on fresh terrain with some agents, which are creatures controlled from outside the world instead of by their
+/// brains, and every step runs one tick of the world
+pub struct Environment<'a> {
+    pub config: &'a EnvironmentConfig,
+    pub terrain: &'a TerrainConfig,
+    pub map: &'a Option<String>,
+    pub rules: &'a Rules,
+    pub seeds: &'a Vec<Seed>,
+    pub world: Option<World>,
+    agents: Vec<Agent>,
+}
+
+impl<'a> Environment<'a> {
+    pub fn new(config: &'a EnvironmentConfig, terrain: &'a TerrainConfig, map: &'a Option<String>, rules: &'a Rules, seeds: &'a Vec<Seed>) -> Environment<'a>
+    {
+        Environment {
+            config: config,
+            terrain: terrain,
+            map: map,
+            rules: rules,
+            seeds: seeds,
+            world: None,
+            agents: vec!(),
+        }
+    }
+
+    /// Starts a new episode and returns the first observation for each agent.  A seed picks the terrain, which is
+    /// otherwise the terrain seed from the command line, or random.  The creatures' starting places and the rest of the
+    /// simulation are random either way
+    pub fn reset(&mut self, seed: Option<usize>) -> Result<Vec<Vec<f64>>, String>
+    {
+        let mut config = self.terrain.clone();
+        if seed.is_some() {
+            config.seed = seed;
+        }
+
+        let terrain = match *self.map {
+            Some(ref map) => mapfile::import(Path::new(map), &config).map_err(|err| format!("error loading map {}: {}", map, err))?,
+            None => Terrain::new(&config),
+        };
+
+        let mut creatures : Vec<Creature> = (0..self.config.agents).map(|_| Creature::generate(&terrain, 1, 0.0, -1, None)).collect();
+        self.agents = creatures.iter().map(|creature| Agent { id: creature.id, eaten: creature.eaten, size: creature.size, done: false }).collect();
+        if self.config.background {
+            match seed::populate(self.seeds, &terrain, 1) {
+                Ok(background) => creatures.extend(background),
+                Err(errors) => return Err(errors.iter().map(|err| err.to_string()).collect::<Vec<String>>().join("\n")),
+            }
+        }
+
+        self.world = Some(World::from_creatures(creatures, terrain, self.rules.clone()));
+        Ok(self.step_results().into_iter().map(|transition| transition.observation).collect())
+    }
+
+    /// Runs one tick with a control for each agent, and returns what happened to each of them
+    pub fn step(&mut self, controls: &Vec<Control>) -> Result<Vec<Transition>, String>
+    {
+        if controls.len() != self.agents.len() {
+            return Err(format!("expected {} controls, one for each agent, but got {}", self.agents.len(), controls.len()));
+        }
+
+        let reproduce = self.rules.reproduction == Reproduction::Brain;
+        for control in controls {
+            if let Control::Action(action) = *control {
+                if self.rules.actions.map_or(true, |actions| !actions.allowed(action, reproduce)) {
+                    return Err(format!("action {:?} isn't allowed without --actions, --attack for attacks, or --reproduction brain for reproducing", action));
+                }
+            }
+        }
+
+        let mut world = match self.world.take() {
+            Some(world) => world,
+            None => return Err(String::from("the environment has to be reset before the first step")),
+        };
+
+        let active : HashMap<CreatureID, Control> = self.agents.iter().zip(controls.iter()).filter(|&(agent, _)| !agent.done).map(|(agent, control)| (agent.id, control.clone())).collect();
+        world.timeslice_with(&active);
+        self.world = Some(world);
+
+        Ok(self.step_results())
+    }
+
+    /// Returns the size of each observation, the number of outputs in an output control, and the number of discrete
+    /// actions if the creatures choose one action at a time, along with which of them are allowed
+    pub fn spec(&self) -> Spec
+    {
+        let reproduce = self.rules.reproduction == Reproduction::Brain;
+        Spec {
+            agents: self.config.agents,
+            observation_size: BRAIN_IN as usize,
+            output_size: BRAIN_OUT as usize,
+            actions: self.rules.actions.map(|_| ACTIONS),
+            allowed_actions: self.rules.actions.map(|actions| actions.allowed_mask(reproduce)),
+        }
+    }
+
+    /// Observes every agent, and works out the rewards since the last time they were observed
+    fn step_results(&mut self) -> Vec<Transition>
+    {
+        let world = match self.world {
+            Some(ref world) => world,
+            None => return vec!(),
+        };
+
+        let heard = if self.rules.signals {
+            signal::hear(&world.creatures, &world.terrain)
+        } else {
+            vec![[0.0; SIGNAL_CHANNELS]; world.creatures.len()]
+        };
+        let index : HashMap<CreatureID, usize> = world.creatures.iter().enumerate().map(|(i, creature)| (creature.id, i)).collect();
+
+        self.agents.iter_mut().map(|agent| {
+            match index.get(&agent.id) {
+                Some(&i) if !agent.done => {
+                    let creature = &world.creatures[i];
+                    let reward = (creature.eaten - agent.eaten) + (creature.size - agent.size);
+                    agent.eaten = creature.eaten;
+                    agent.size = creature.size;
+                    Transition { observation: creature.sense(&world.terrain, heard[i]), reward: reward, done: false }
+                },
+                _ => {
+                    // the agent died on this step if it wasn't already done, and loses everything it had
+                    let reward = if agent.done { 0.0 } else { -agent.size };
+                    agent.done = true;
+                    agent.size = 0.0;
+                    Transition { observation: vec![0.0; BRAIN_IN as usize], reward: reward, done: true }
+                },
+            }
+        }).collect()
+    }
+}
+
+
+#[derive(Serialize)]
+pub struct Spec {
+    pub agents: usize,
+    pub observation_size: usize,
+    pub output_size: usize,
+    pub actions: Option<usize>,
+    /// Whether each discrete action can be taken, in the order of the action indices
+    pub allowed_actions: Option<Vec<bool>>,
+}
+
+/// A control as sent by a client, which is either the index of a discrete action or a list of outputs
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum WireControl {
+    Action(usize),
+    Outputs(Vec<f64>),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "command", rename_all = "lowercase")]
+enum Request {
+    Reset { seed: Option<usize> },
+    Step { controls: Vec<WireControl> },
+    Spec,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum Reply {
+    Reset { observations: Vec<Vec<f64>> },
+    Step { observations: Vec<Vec<f64>>, rewards: Vec<f64>, dones: Vec<bool> },
+    Spec(Spec),
+    Error { error: String },
+}
+
+/// Serves the environment to clients on a local port, one client at a time.  Each request and reply is one line of JSON
+pub fn serve(environment: &mut Environment) -> Result<(), io::Error>
+{
+    let listener = TcpListener::bind(("127.0.0.1", environment.config.port))?;
+    println!("Serving the environment on port {}", environment.config.port);
+
+    for stream in listener.incoming() {
+        let stream = stream?;
+        if let Err(err) = handle_client(environment, stream) {
+            println!("Client disconnected: {}", err);
+        }
+    }
+    return Ok(());
+}
+
+fn handle_client(environment: &mut Environment, stream: TcpStream) -> Result<(), io::Error>
+{
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let reply = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => handle_request(environment, request),
+            Err(err) => Reply::Error { error: format!("invalid request: {}", err) },
+        };
+        writeln!(writer, "{}", serde_json::to_string(&reply).map_err(|err| io::Error::new(io::ErrorKind::Other, err))?)?;
+    }
+    return Ok(());
+}
+
+fn handle_request(environment: &mut Environment, request: Request) -> Reply
+{
+    match request {
+        Request::Reset { seed } => match environment.reset(seed) {
+            Ok(observations) => Reply::Reset { observations: observations },
+            Err(err) => Reply::Error { error: err },
+        },
+        Request::Step { controls } => {
+            let mut converted = vec!();
+            for control in controls {
+                converted.push(match control {
+                    WireControl::Action(index) => match Action::from_index(index) {
+                        Some(action) => Control::Action(action),
+                        None => return Reply::Error { error: format!("unknown action {}", index) },
+                    },
+                    WireControl::Outputs(outputs) => Control::Outputs(outputs),
+                });
+            }
+
+            match environment.step(&converted) {
+                Ok(transitions) => Reply::Step {
+                    rewards: transitions.iter().map(|transition| transition.reward).collect(),
+                    dones: transitions.iter().map(|transition| transition.done).collect(),
+                    observations: transitions.into_iter().map(|transition| transition.observation).collect(),
+                },
+                Err(err) => Reply::Error { error: err },
+            }
+        },
+        Request::Spec => Reply::Spec(environment.spec()),
+    }
+}
@@ -0,0 +1,325 @@
+
+use std::collections::HashMap;
+use std::fs::{ self, OpenOptions };
+use std::io::{ self, Write };
+use std::path::{ Path, PathBuf };
+
+extern crate rand;
+use self::rand::Rng;
+
+use world::*;
+use seed::{ self, Seed };
+use generator::TerrainConfig;
+use mapfile;
+use library::SavedCreature;
+use format::{ self, Format };
+use stats::{ self, Summary };
+use novelty::{ Archive, Behaviour };
+
+
+const LOG_FILE : &'static str = "fitness.csv";
+
+/// How a brain is scored at the end of an episode
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Fitness {
+    /// The total food eaten
+    Eaten,
+    /// The number of ticks survived
+    Survival,
+    /// How different the brain's behaviour is from the rest of the generation and the archive of novel behaviours
+    Novelty,
+}
+
+impl Fitness {
+    pub fn from_str(name: &str) -> Option<Fitness>
+    {
+        match name {
+            "eaten" => Some(Fitness::Eaten),
+            "survival" => Some(Fitness::Survival),
+            "novelty" => Some(Fitness::Novelty),
+            _ => None,
+        }
+    }
+}
+
+/// What a brain did during an episode
+struct Outcome {
+    eaten: f64,
+    survived: WorldTime,
+    behaviour: Behaviour,
+}
+
+/// Who a brain shares the terrain with while it's being evaluated
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Episode {
+    /// Each brain is evaluated alone on its own fresh terrain
+    Isolated,
+    /// The whole population is evaluated together on one fresh terrain
+    Shared,
+}
+
+impl Episode {
+    pub fn from_str(name: &str) -> Option<Episode>
+    {
+        match name {
+            "isolated" => Some(Episode::Isolated),
+            "shared" => Some(Episode::Shared),
+            _ => None,
+        }
+    }
+}
+
+pub struct EvolutionConfig {
+    pub population: usize,
+    pub generations: usize,
+    /// The length of each episode in ticks
+    pub ticks: WorldTime,
+    pub episode: Episode,
+    pub fitness: Fitness,
+    /// The number of brains picked at random for each tournament, where the best of them becomes a parent
+    pub tournament: usize,
+    /// The number of best brains copied unchanged into the next generation
+    pub elitism: usize,
+    /// The directory that checkpoints and the fitness log are written to
+    pub dir: String,
+    /// How many generations pass between each checkpoint
+    pub checkpoint_interval: usize,
+    /// Carry on from the latest checkpoint in the directory
+    pub resume: bool,
+}
+
+impl EvolutionConfig {
+    pub fn new() -> EvolutionConfig
+    {
+        EvolutionConfig {
+            population: 50,
+            generations: 100,
+            ticks: 2000,
+            episode: Episode::Isolated,
+            fitness: Fitness::Eaten,
+            tournament: 3,
+            elitism: 2,
+            dir: String::from("evolution"),
+            checkpoint_interval: 10,
+            resume: false,
+        }
+    }
+}
+
+
+/// Breeds brains offline with a generational genetic algorithm.  Each generation is scored in episodes on fresh terrain,
+/// and the next generation is made of the best few brains unchanged, and mutated children of tournament winners
+pub struct Evolution<'a> {
+    pub config: &'a EvolutionConfig,
+    pub terrain: &'a TerrainConfig,
+    pub map: &'a Option<String>,
+    pub rules: &'a Rules,
+    pub format: Format,
+}
+
+impl<'a> Evolution<'a> {
+    pub fn run(&self, seeds: &Vec<Seed>) -> Result<(), String>
+    {
+        let dir = PathBuf::from(&self.config.dir);
+        fs::create_dir_all(&dir).map_err(|err| format!("error creating {}: {}", dir.display(), err))?;
+
+        let (mut brains, first) = if self.config.resume {
+            self.resume(&dir)?
+        } else {
+            // a new run starts a new fitness log, rather than adding to the log of an earlier run
+            let log = dir.join(LOG_FILE);
+            if log.exists() {
+                fs::remove_file(&log).map_err(|err| format!("error removing {}: {}", log.display(), err))?;
+            }
+            (self.initial_brains(seeds)?, 0)
+        };
+
+        let mut archive = Archive::new();
+        for generation in first..self.config.generations {
+            let outcomes = self.evaluate(&brains)?;
+            let scores = self.score(&outcomes, &mut archive);
+
+            let mut ranked : Vec<(f64, Brain)> = scores.into_iter().zip(brains.into_iter()).collect();
+            ranked.sort_by(|a, b| stats::highest_first(a.0, b.0));
+
+            let summary = Summary::from(ranked.iter().map(|&(score, _)| score).collect());
+            println!("Generation {}: best {:.4}, mean {:.4}, median {:.4}", generation + 1, summary.max, summary.mean, summary.median);
+            self.log(&dir, generation + 1, &summary).map_err(|err| format!("error writing fitness log: {}", err))?;
+
+            let last = generation + 1 == self.config.generations;
+            if (generation + 1) % self.config.checkpoint_interval.max(1) == 0 || last {
+                let path = self.checkpoint(&dir, generation + 1, &ranked).map_err(|err| format!("error writing checkpoint: {}", err))?;
+                println!("Saved checkpoint to {}", path.display());
+            }
+
+            brains = self.breed(&ranked);
+        }
+        return Ok(());
+    }
+
+    /// Makes the first generation from the seeds, cycling through them with mutated copies if there aren't enough
+    fn initial_brains(&self, seeds: &Vec<Seed>) -> Result<Vec<Brain>, String>
+    {
+        let terrain = self.fresh_terrain(None)?;
+        let loaded : Vec<Brain> = match seed::populate(seeds, &terrain, 1) {
+            Ok(creatures) => creatures.into_iter().map(|creature| creature.brain).collect(),
+            Err(errors) => return Err(errors.iter().map(|err| err.to_string()).collect::<Vec<String>>().join("\n")),
+        };
+
+        if loaded.len() <= 0 {
+            return Err(String::from("no brains to start evolving from"));
+        }
+        return Ok(self.fill(loaded));
+    }
+
+    /// Takes up to a population's worth of brains, and makes up any shortfall with mutated copies of them
+    fn fill(&self, loaded: Vec<Brain>) -> Vec<Brain>
+    {
+        let mut brains : Vec<Brain> = loaded.iter().take(self.config.population).cloned().collect();
+        while brains.len() < self.config.population {
+            let brain = loaded[brains.len() % loaded.len()].spawn();
+            brains.push(brain);
+        }
+        return brains;
+    }
+
+    /// Breeds the next generation from the latest checkpoint, and returns the number of generations already done
+    fn resume(&self, dir: &Path) -> Result<(Vec<Brain>, usize), String>
+    {
+        let mut latest = None;
+        for entry in fs::read_dir(dir).map_err(|err| format!("error reading {}: {}", dir.display(), err))? {
+            let path = entry.map_err(|err| err.to_string())?.path();
+            let generation = path.file_name().and_then(|name| name.to_str()).filter(|name| name.starts_with("gen-")).and_then(|name| name[4..].parse::<usize>().ok());
+            if let Some(generation) = generation {
+                if path.is_dir() && latest.as_ref().map_or(true, |&(best, _)| generation > best) {
+                    latest = Some((generation, path));
+                }
+            }
+        }
+
+        let (generation, path) = match latest {
+            Some(latest) => latest,
+            None => return Err(format!("no checkpoints found in {}", dir.display())),
+        };
+
+        let mut errors = vec!();
+        let brains = seed::load_brains(&path.to_string_lossy(), &mut errors).unwrap_or(vec!());
+        if errors.len() > 0 || brains.len() <= 0 {
+            return Err(format!("error loading checkpoint {}: {}", path.display(), errors.iter().map(|err| err.to_string()).collect::<Vec<String>>().join(", ")));
+        }
+        println!("Resuming from {} after generation {}", path.display(), generation);
+
+        // the checkpoint is named by rank, so the next generation can be bred from it without scoring it again
+        let ranked = brains.into_iter().map(|brain| (0.0, brain)).collect();
+        return Ok((self.breed(&ranked), generation));
+    }
+
+    /// Makes a terrain for an episode, from the map if one was given.  Without a terrain seed, each generation is evaluated
+    /// on a different random terrain, but every episode in the generation uses the same one
+    fn fresh_terrain(&self, seed: Option<usize>) -> Result<Terrain, String>
+    {
+        let mut config = self.terrain.clone();
+        if config.seed.is_none() {
+            config.seed = seed.or(Some(rand::thread_rng().gen()));
+        }
+
+        match *self.map {
+            Some(ref map) => mapfile::import(Path::new(map), &config).map_err(|err| format!("error loading map {}: {}", map, err)),
+            None => Ok(Terrain::new(&config)),
+        }
+    }
+
+    fn evaluate(&self, brains: &Vec<Brain>) -> Result<Vec<Outcome>, String>
+    {
+        let seed = rand::thread_rng().gen();
+        match self.config.episode {
+            Episode::Isolated => {
+                let mut outcomes = vec!();
+                for brain in brains {
+                    outcomes.extend(self.episode(self.fresh_terrain(Some(seed))?, &vec!(brain.clone())));
+                }
+                Ok(outcomes)
+            },
+            Episode::Shared => Ok(self.episode(self.fresh_terrain(Some(seed))?, brains)),
+        }
+    }
+
+    /// Runs one episode with the given brains, and returns what each of them did.  Children born during the episode are
+    /// removed straight away, so that only the brains being evaluated share the terrain
+    fn episode(&self, terrain: Terrain, brains: &Vec<Brain>) -> Vec<Outcome>
+    {
+        let creatures : Vec<Creature> = brains.iter().map(|brain| Creature::generate(&terrain, 1, 0.5, -1, Some(brain.clone()))).collect();
+        let ids : Vec<CreatureID> = creatures.iter().map(|creature| creature.id).collect();
+        let mut world = World::from_creatures(creatures, terrain, self.rules.clone());
+
+        let mut results : HashMap<CreatureID, Outcome> = ids.iter().map(|&id| (id, Outcome { eaten: 0.0, survived: 0, behaviour: Behaviour::default() })).collect();
+        for _ in 0..self.config.ticks {
+            world.timeslice();
+            world.creatures.retain(|creature| results.contains_key(&creature.id));
+            // the creatures are removed from the world when they die, so keep what they did up to now
+            for creature in &world.creatures {
+                results.insert(creature.id, Outcome { eaten: creature.eaten, survived: world.time - creature.birthday, behaviour: creature.behaviour.clone() });
+            }
+
+            if world.creatures.len() <= 0 {
+                break;
+            }
+        }
+
+        ids.iter().map(|id| results.remove(id).unwrap()).collect()
+    }
+
+    /// Scores each outcome by the fitness.  With a novelty bonus, the scores are raised by up to that multiple of each
+    /// brain's novelty, in the same way as the bonus to reproduction in the open world
+    fn score(&self, outcomes: &Vec<Outcome>, archive: &mut Archive) -> Vec<f64>
+    {
+        let descriptors = outcomes.iter().map(|outcome| outcome.behaviour.descriptor()).collect();
+        let novelty = archive.measure(&descriptors);
+
+        outcomes.iter().zip(novelty.iter()).map(|(outcome, &novelty)| {
+            let score = match self.config.fitness {
+                Fitness::Eaten => outcome.eaten,
+                Fitness::Survival => outcome.survived as f64,
+                Fitness::Novelty => return novelty,
+            };
+            score * (1.0 + self.rules.novelty.unwrap_or(0.0) * novelty)
+        }).collect()
+    }
+
+    /// Makes the next generation from a population ranked from best to worst
+    fn breed(&self, ranked: &Vec<(f64, Brain)>) -> Vec<Brain>
+    {
+        let mut brains : Vec<Brain> = ranked.iter().take(self.config.elitism).map(|&(_, ref brain)| brain.clone()).collect();
+        while brains.len() < self.config.population {
+            // the population is ranked, so the lowest index drawn is the best of the tournament
+            let winner = (0..self.config.tournament.max(1)).map(|_| rand::thread_rng().gen_range(0, ranked.len())).min().unwrap();
+            brains.push(ranked[winner].1.spawn());
+        }
+        return brains;
+    }
+
+    /// Saves every brain in the population, named by rank, so the directory can be loaded back into the open world
+    fn checkpoint(&self, dir: &Path, generation: usize, ranked: &Vec<(f64, Brain)>) -> Result<PathBuf, io::Error>
+    {
+        let path = dir.join(format!("gen-{:05}", generation));
+        fs::create_dir_all(&path)?;
+        for (i, &(_, ref brain)) in ranked.iter().enumerate() {
+            let saved = SavedCreature { meta: None, brain: brain.clone() };
+            format::write(&path.join(format!("{:04}.{}", i + 1, self.format.extension())), &saved)?;
+        }
+        return Ok(path);
+    }
+
+    /// Appends a line to the fitness log, starting a new log with a header
+    fn log(&self, dir: &Path, generation: usize, summary: &Summary) -> Result<(), io::Error>
+    {
+        let path = dir.join(LOG_FILE);
+        let exists = path.exists();
+        let mut log = OpenOptions::new().create(true).append(true).open(&path)?;
+        if !exists {
+            writeln!(log, "generation,best,mean,median")?;
+        }
+        writeln!(log, "{},{},{},{}", generation, summary.max, summary.mean, summary.median)
+    }
+}
+
@@ -0,0 +1,124 @@
+
+use std::fs::{ self, File };
+use std::io::{ self, BufReader, BufWriter, Write, ErrorKind };
+use std::path::Path;
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json;
+use bincode;
+use rmp_serde;
+
+
+/// The file formats that simulation data can be saved in.  JSON is readable and compatible with older files, while
+/// bincode and MessagePack are much smaller and faster for large snapshots and archives.  Bincode doesn't record the fields
+/// it writes, so its files can't be read once any struct changes.  MessagePack writes structs as lists of fields, so older
+/// files can be read as long as new fields are only added at the end of a struct, with a default
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Format {
+    Json,
+    Bincode,
+    MessagePack,
+}
+
+impl Format {
+    pub fn from_str(name: &str) -> Option<Format>
+    {
+        match name {
+            "json" => Some(Format::Json),
+            "bin" | "bincode" => Some(Format::Bincode),
+            "msgpack" | "messagepack" => Some(Format::MessagePack),
+            _ => None,
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Option<Format>
+    {
+        path.extension().and_then(|ext| ext.to_str()).and_then(|ext| Format::from_str(ext))
+    }
+
+    pub fn extension(&self) -> &'static str
+    {
+        match *self {
+            Format::Json => "json",
+            Format::Bincode => "bin",
+            Format::MessagePack => "msgpack",
+        }
+    }
+}
+
+/// The name that archives of many creatures end with, before the extension, such as `creatures.archive.bin`
+const ARCHIVE_SUFFIX : &'static str = ".archive";
+
+/// Returns true if the path is a file that holds a single value in one of the formats, and not an archive
+pub fn is_data_file(path: &Path) -> bool
+{
+    path.is_file() && Format::from_path(path).is_some() && !is_archive(path)
+}
+
+/// Returns true if the path is an archive, which holds a list of creatures rather than a single one
+pub fn is_archive(path: &Path) -> bool
+{
+    path.is_file() && Format::from_path(path).is_some() && path.file_stem().and_then(|stem| stem.to_str()).map_or(false, |stem| stem.ends_with(ARCHIVE_SUFFIX))
+}
+
+/// The file name of an archive in the given format
+pub fn archive_name(name: &str, format: Format) -> String
+{
+    format!("{}{}.{}", name, ARCHIVE_SUFFIX, format.extension())
+}
+
+
+/// Writes a value to a file in the format given by the file's extension.  The file must not already exist
+pub fn write_new<T: Serialize>(path: &Path, value: &T) -> Result<(), io::Error>
+{
+    let f = fs::OpenOptions::new().write(true).create_new(true).open(path)?;
+    write_to(f, path, value)
+}
+
+/// Writes a value to a file in the format given by the file's extension, replacing the file if it exists
+pub fn write<T: Serialize>(path: &Path, value: &T) -> Result<(), io::Error>
+{
+    let f = File::create(path)?;
+    write_to(f, path, value)
+}
+
+fn write_to<T: Serialize>(f: File, path: &Path, value: &T) -> Result<(), io::Error>
+{
+    let mut writer = BufWriter::new(f);
+    let result = match format_of(path)? {
+        Format::Json => serde_json::to_writer_pretty(&mut writer, value).map_err(|err| err.to_string()),
+        Format::Bincode => bincode::serialize_into(&mut writer, value).map_err(|err| err.to_string()),
+        Format::MessagePack => rmp_serde::encode::write(&mut writer, value).map_err(|err| err.to_string()),
+    };
+
+    match result {
+        Ok(()) => writer.flush(),
+        Err(err) => Err(io::Error::new(ErrorKind::InvalidData, format!("error encoding {}: {}", path.display(), err))),
+    }
+}
+
+/// Reads a value from a file in the format given by the file's extension
+pub fn read<T: DeserializeOwned>(path: &Path) -> Result<T, io::Error>
+{
+    let reader = BufReader::new(File::open(path)?);
+    let result = match format_of(path)? {
+        Format::Json => serde_json::from_reader(reader).map_err(|err| err.to_string()),
+        Format::Bincode => bincode::deserialize_from(reader).map_err(|err| err.to_string()),
+        Format::MessagePack => rmp_serde::from_read(reader).map_err(|err| err.to_string()),
+    };
+
+    match result {
+        Ok(value) => Ok(value),
+        Err(err) => Err(io::Error::new(ErrorKind::InvalidData, format!("error decoding {}: {}", path.display(), err))),
+    }
+}
+
+fn format_of(path: &Path) -> Result<Format, io::Error>
+{
+    match Format::from_path(path) {
+        Some(format) => Ok(format),
+        None => Err(io::Error::new(ErrorKind::InvalidInput, format!("unknown file format: {}", path.display()))),
+    }
+}
+
@@ -0,0 +1,267 @@
+
+use std::f64;
+
+use resource::Resource;
+use climate::ClimateConfig;
+use vegetation::Vegetation;
+use world::Boundary;
+use scent::ScentConfig;
+
+extern crate rand;
+use self::rand::{ Rng, SeedableRng, StdRng };
+
+
+pub const MAX_TTYPE : i32 = 5;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Generator {
+    /// The original generator, which averages the tiles to the left and above with a random step
+    Walk,
+    /// Fractal Perlin noise, which makes smooth hills of fertility
+    Noise,
+    /// Random fertile cells smoothed with cellular automata into blobs of growth and desert
+    Cellular,
+    /// Patches of a random fertility around randomly placed points
+    Voronoi,
+}
+
+impl Generator {
+    pub fn from_str(name: &str) -> Option<Generator>
+    {
+        match name {
+            "walk" => Some(Generator::Walk),
+            "noise" | "perlin" => Some(Generator::Noise),
+            "cellular" => Some(Generator::Cellular),
+            "voronoi" => Some(Generator::Voronoi),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TerrainConfig {
+    pub generator: Generator,
+    /// The seed for the generator, or a random one if not given
+    pub seed: Option<usize>,
+    /// The approximate size in tiles of the fertile regions and deserts
+    pub scale: f64,
+    /// The types of food that grow on the tiles
+    pub resources: Vec<Resource>,
+    pub boundary: Boundary,
+    pub climate: ClimateConfig,
+    /// How many ticks between each step of food growth
+    pub growth_interval: u64,
+    /// Grows food with the vegetation model instead of the original independent growth on each tile
+    pub vegetation: Option<Vegetation>,
+    /// Adds a field of scent that creatures can leave and sense
+    pub scent: Option<ScentConfig>,
+}
+
+impl TerrainConfig {
+    pub fn new() -> TerrainConfig
+    {
+        TerrainConfig {
+            generator: Generator::Walk,
+            seed: None,
+            scale: 20.0,
+            resources: vec!(Resource::food()),
+            boundary: Boundary::Torus,
+            climate: ClimateConfig::new(),
+            growth_interval: 10,
+            vegetation: None,
+            scent: None,
+        }
+    }
+}
+
+
+/// Generates the tile types for a terrain of the given size, indexed by column and then row
+pub fn generate(config: &TerrainConfig, size: [usize; 2]) -> Vec<Vec<i32>>
+{
+    let seed = match config.seed {
+        Some(seed) => seed,
+        None => {
+            let seed = rand::thread_rng().gen();
+            println!("Terrain seed: {}", seed);
+            seed
+        },
+    };
+    let mut rng : StdRng = SeedableRng::from_seed(&[seed][..]);
+    let scale = config.scale.max(1.0);
+
+    match config.generator {
+        Generator::Walk => walk(&mut rng, size),
+        Generator::Noise => noise(&mut rng, size, scale),
+        Generator::Cellular => cellular(&mut rng, size, scale),
+        Generator::Voronoi => voronoi(&mut rng, size, scale, config.boundary),
+    }
+}
+
+fn walk(rng: &mut StdRng, size: [usize; 2]) -> Vec<Vec<i32>>
+{
+    let mut ttypes = vec![vec![0; size[1]]; size[0]];
+    for col in 0..size[0] {
+        for row in 0..size[1] {
+            let left = if col > 0 { ttypes[col - 1][row] } else { rng.gen_range(0, MAX_TTYPE) };
+            let top = if row > 0 { ttypes[col][row - 1] } else { rng.gen_range(0, MAX_TTYPE) };
+            let middle = (left + top) / 2;
+            let random = rng.gen_range(0.0, 1.0);
+
+            ttypes[col][row] = if random <= 0.25 {
+                if middle > 0 { middle - 1 } else { 1 }
+            }
+            else if random >= 0.75 {
+                if middle < MAX_TTYPE { middle + 1 } else { MAX_TTYPE }
+            }
+            else {
+                middle
+            };
+        }
+    }
+    return ttypes;
+}
+
+fn noise(rng: &mut StdRng, size: [usize; 2], scale: f64) -> Vec<Vec<i32>>
+{
+    let perlin = Perlin::new(rng);
+
+    let mut ttypes = vec![vec![0; size[1]]; size[0]];
+    for col in 0..size[0] {
+        for row in 0..size[1] {
+            // fractal noise, with each octave adding finer detail at half the strength
+            let (mut value, mut amplitude, mut frequency) = (0.0, 1.0, 1.0 / scale);
+            for _ in 0..4 {
+                value += amplitude * perlin.get(col as f64 * frequency, row as f64 * frequency);
+                amplitude /= 2.0;
+                frequency *= 2.0;
+            }
+
+            ttypes[col][row] = to_ttype(value * 0.6 + 0.5);
+        }
+    }
+    return ttypes;
+}
+
+fn cellular(rng: &mut StdRng, size: [usize; 2], scale: f64) -> Vec<Vec<i32>>
+{
+    // the automaton runs on a grid of coarse cells, so that the blobs it makes are about the size of the scale
+    let cell = (scale / 5.0).max(1.0) as usize;
+    let grid = [ (size[0] + cell - 1) / cell, (size[1] + cell - 1) / cell ];
+
+    let mut fertile : Vec<Vec<bool>> = (0..grid[0]).map(|_| (0..grid[1]).map(|_| rng.gen_range(0.0, 1.0) < 0.5).collect()).collect();
+    for _ in 0..5 {
+        let mut next = fertile.clone();
+        for x in 0..grid[0] {
+            for y in 0..grid[1] {
+                let mut neighbours = 0;
+                for dx in 0..3 {
+                    for dy in 0..3 {
+                        if (dx != 1 || dy != 1) && fertile[(x + grid[0] + dx - 1) % grid[0]][(y + grid[1] + dy - 1) % grid[1]] {
+                            neighbours += 1;
+                        }
+                    }
+                }
+                next[x][y] = if neighbours > 4 { true } else if neighbours < 4 { false } else { fertile[x][y] };
+            }
+        }
+        fertile = next;
+    }
+
+    let mut ttypes = vec![vec![0; size[1]]; size[0]];
+    for col in 0..size[0] {
+        for row in 0..size[1] {
+            ttypes[col][row] = if fertile[col / cell][row / cell] { rng.gen_range(MAX_TTYPE - 1, MAX_TTYPE + 1) } else { rng.gen_range(0, 2) };
+        }
+    }
+    return ttypes;
+}
+
+fn voronoi(rng: &mut StdRng, size: [usize; 2], scale: f64, boundary: Boundary) -> Vec<Vec<i32>>
+{
+    let count = ((size[0] * size[1]) as f64 / (scale * scale)).max(1.0) as usize;
+    let sites : Vec<(f64, f64, i32)> = (0..count).map(|_| (rng.gen_range(0.0, size[0] as f64), rng.gen_range(0.0, size[1] as f64), rng.gen_range(0, MAX_TTYPE + 1))).collect();
+
+    let mut ttypes = vec![vec![0; size[1]]; size[0]];
+    for col in 0..size[0] {
+        for row in 0..size[1] {
+            let mut closest = (f64::MAX, 0);
+            for &(x, y, ttype) in &sites {
+                let distance = boundary.distance([ col as f64, row as f64 ], [ x, y ], size);
+                if distance < closest.0 {
+                    closest = (distance, ttype);
+                }
+            }
+            ttypes[col][row] = closest.1;
+        }
+    }
+    return ttypes;
+}
+
+/// Converts a value between 0.0 and 1.0 into a tile type
+fn to_ttype(value: f64) -> i32
+{
+    ((value * (MAX_TTYPE + 1) as f64) as i32).min(MAX_TTYPE).max(0)
+}
+
+
+/// Two dimensional gradient noise, with a random permutation table
+struct Perlin {
+    perm: Vec<usize>,
+}
+
+impl Perlin {
+    fn new(rng: &mut StdRng) -> Perlin
+    {
+        let mut perm : Vec<usize> = (0..256).collect();
+        rng.shuffle(&mut perm);
+        let copy = perm.clone();
+        perm.extend(copy);
+
+        Perlin {
+            perm: perm,
+        }
+    }
+
+    /// Returns the noise at a point, roughly between -1.0 and 1.0
+    fn get(&self, x: f64, y: f64) -> f64
+    {
+        let (x0, y0) = (x.floor(), y.floor());
+        let (xi, yi) = ((x0 as i64 & 255) as usize, (y0 as i64 & 255) as usize);
+        let (xf, yf) = (x - x0, y - y0);
+
+        let corner = |dx: usize, dy: usize| {
+            let hash = self.perm[self.perm[xi + dx] + yi + dy];
+            gradient(hash, xf - dx as f64, yf - dy as f64)
+        };
+
+        let (u, v) = (fade(xf), fade(yf));
+        let top = lerp(u, corner(0, 0), corner(1, 0));
+        let bottom = lerp(u, corner(0, 1), corner(1, 1));
+        return lerp(v, top, bottom);
+    }
+}
+
+fn gradient(hash: usize, x: f64, y: f64) -> f64
+{
+    match hash & 7 {
+        0 => x + y,
+        1 => x - y,
+        2 => -x + y,
+        3 => -x - y,
+        4 => x,
+        5 => -x,
+        6 => y,
+        _ => -y,
+    }
+}
+
+fn fade(t: f64) -> f64
+{
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: f64, a: f64, b: f64) -> f64
+{
+    a + t * (b - a)
+}
+
@@ -0,0 +1,224 @@
+
+use std::collections::{ BTreeMap, HashSet };
+use std::fs;
+use std::io::{ self, ErrorKind };
+use std::path::{ Path, PathBuf };
+
+use world::*;
+use dump::RankBy;
+use format::{ self, Format };
+use stats;
+
+
+const INDEX_FILE : &'static str = "index.json";
+
+/// How often the living creatures are checked for a place in the hall of fame
+pub const HALL_INTERVAL : WorldTime = 1000;
+
+pub const METRICS : [RankBy; 4] = [ RankBy::Age, RankBy::Spawns, RankBy::Descendants, RankBy::EatenRate ];
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub run: String,
+    pub id: CreatureID,
+    pub score: f64,
+    pub file: String,
+}
+
+/// The best creatures ever seen on each metric, kept across runs in a directory.  The full state of each creature is saved
+/// in its own file, and an index.json file lists the ranking for each metric
+pub struct HallOfFame {
+    pub dir: PathBuf,
+    pub size: usize,
+    pub format: Format,
+    pub run: String,
+    pub rankings: BTreeMap<String, Vec<Entry>>,
+
+    last_time: WorldTime,
+}
+
+impl HallOfFame {
+    /// Opens the hall of fame in a directory, carrying on from the rankings already saved there if there are any.  A new
+    /// hall of fame has to be given a directory that's empty or doesn't exist yet, so that no other files are mixed in
+    pub fn open(dir: &str, size: usize, format: Format, run: &str) -> Result<HallOfFame, io::Error>
+    {
+        let dir = PathBuf::from(dir);
+        let index = dir.join(INDEX_FILE);
+        let rankings = if index.exists() {
+            format::read(&index)?
+        } else if dir.exists() && fs::read_dir(&dir)?.next().is_some() {
+            return Err(io::Error::new(ErrorKind::AlreadyExists, format!("{} isn't empty, and isn't a hall of fame", dir.display())));
+        } else {
+            BTreeMap::new()
+        };
+
+        Ok(HallOfFame {
+            dir: dir,
+            size: size,
+            format: format,
+            run: run.to_string(),
+            rankings: rankings,
+
+            last_time: 0,
+        })
+    }
+
+    /// Returns true if the rankings are due to be updated at this time, which is only once even if the time stops while the
+    /// world is paused or has died out
+    pub fn due(&self, time: WorldTime) -> bool
+    {
+        time % HALL_INTERVAL == 0 && time != self.last_time
+    }
+
+    /// Ranks the living creatures against the creatures already in the hall of fame, and saves the creatures that make it
+    /// onto any of the rankings.  Files of creatures that have dropped off every ranking are removed
+    pub fn update(&mut self, world: &World) -> Result<(), io::Error>
+    {
+        fs::create_dir_all(&self.dir)?;
+        self.last_time = world.time;
+        let listed = self.files();
+
+        let mut saving = HashSet::new();
+        for metric in &METRICS {
+            let mut ranking : Vec<Entry> = self.rankings.get(metric.name()).cloned().unwrap_or(vec!());
+            for creature in &world.creatures {
+                let score = metric.score(world, creature);
+                let file = format!("{}-{}.{}", self.run, creature.id, self.format.extension());
+                match ranking.iter_mut().find(|entry| entry.run == self.run && entry.id == creature.id) {
+                    Some(entry) => { entry.score = score; entry.file = file; },
+                    None => ranking.push(Entry { run: self.run.clone(), id: creature.id, score: score, file: file }),
+                }
+            }
+
+            ranking.sort_by(|a, b| stats::highest_first(a.score, b.score));
+            ranking.truncate(self.size);
+
+            for entry in &ranking {
+                if entry.run == self.run {
+                    saving.insert(entry.id);
+                }
+            }
+            self.rankings.insert(metric.name().to_string(), ranking);
+        }
+
+        // only the creatures that are still alive have changed since they were last saved
+        for creature in world.creatures.iter().filter(|creature| saving.contains(&creature.id)) {
+            let file = format!("{}-{}.{}", self.run, creature.id, self.format.extension());
+            format::write(&self.dir.join(file), creature)?;
+        }
+
+        // only files that the index listed are removed, so nothing else in the directory is ever touched
+        let kept = self.files();
+        for file in listed.difference(&kept) {
+            if let Err(err) = fs::remove_file(self.dir.join(file)) {
+                if err.kind() != ErrorKind::NotFound {
+                    return Err(err);
+                }
+            }
+        }
+
+        format::write(&self.dir.join(INDEX_FILE), &self.rankings)
+    }
+
+    /// Returns the file names of the creatures on any of the rankings
+    fn files(&self) -> HashSet<String>
+    {
+        self.rankings.values().flat_map(|ranking| ranking.iter().map(|entry| entry.file.clone())).collect()
+    }
+
+    /// Prints the top few creatures on each metric
+    pub fn print(&self)
+    {
+        for (metric, ranking) in &self.rankings {
+            let best : Vec<String> = ranking.iter().take(3).map(|entry| format!("{} {} ({:.4})", entry.run, entry.id, entry.score)).collect();
+            println!("Hall of fame, {}: {}", metric, best.join(", "));
+        }
+    }
+}
+
+
+/// Returns true if the directory holds a hall of fame
+pub fn is_hall(dir: &Path) -> bool
+{
+    dir.join(INDEX_FILE).is_file()
+}
+
+/// Loads the brains of every creature in a hall of fame, once each, even if it's on more than one ranking
+pub fn load_brains(dir: &Path) -> Result<Vec<Brain>, io::Error>
+{
+    let rankings : BTreeMap<String, Vec<Entry>> = format::read(&dir.join(INDEX_FILE))?;
+
+    let mut files : Vec<String> = rankings.values().flat_map(|ranking| ranking.iter().map(|entry| entry.file.clone())).collect();
+    files.sort();
+    files.dedup();
+    if files.len() <= 0 {
+        return Err(io::Error::new(ErrorKind::NotFound, format!("hall of fame {} is empty", dir.display())));
+    }
+
+    let mut brains = vec!();
+    for file in files {
+        let creature : Creature = format::read(&dir.join(file))?;
+        brains.push(creature.brain);
+    }
+    return Ok(brains);
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::process;
+    use generator::TerrainConfig;
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf
+    {
+        let dir = env::temp_dir().join(format!("hall-test-{}-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        return dir;
+    }
+
+    #[test]
+    fn new_halls_need_an_empty_directory()
+    {
+        let dir = temp_dir("open");
+        assert!(HallOfFame::open(&dir.to_string_lossy(), 10, Format::Json, "run").is_ok());
+
+        fs::create_dir_all(&dir).unwrap();
+        assert!(HallOfFame::open(&dir.to_string_lossy(), 10, Format::Json, "run").is_ok());
+
+        fs::write(dir.join("notes.json"), "{}").unwrap();
+        assert!(HallOfFame::open(&dir.to_string_lossy(), 10, Format::Json, "run").is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn only_files_that_drop_off_are_removed()
+    {
+        with_terrain(TerrainConfig::new(), |terrain| {
+            let creatures = vec!(Creature::generate(&terrain, 1, 0.5, -1, None));
+            let mut world = World::from_creatures(creatures, terrain, Rules::new());
+            world.time = HALL_INTERVAL;
+
+            let dir = temp_dir("update");
+            let mut hall = HallOfFame::open(&dir.to_string_lossy(), 1, Format::Json, "run").unwrap();
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("old-1.json"), "{}").unwrap();
+            fs::write(dir.join("notes.json"), "{}").unwrap();
+            let old = Entry { run: String::from("old"), id: 1, score: f64::NEG_INFINITY, file: String::from("old-1.json") };
+            hall.rankings.insert(RankBy::Age.name().to_string(), vec!(old));
+
+            assert!(hall.due(world.time));
+            hall.update(&world).unwrap();
+            // the time stands still while the world is paused, but the hall is only updated once
+            assert!(!hall.due(world.time));
+            assert!(!dir.join("old-1.json").exists());
+            assert!(dir.join("notes.json").exists());
+            assert!(dir.join(format!("run-{}.json", world.creatures[0].id)).exists());
+
+            fs::remove_dir_all(&dir).unwrap();
+        });
+    }
+}
@@ -0,0 +1,290 @@
+
+use std::fs::{ self, File };
+use std::io::{ self, Read, ErrorKind };
+use std::path::{ Path, PathBuf };
+use std::time::{ SystemTime, UNIX_EPOCH };
+
+use serde_json::{ self, Value };
+
+use world::*;
+use format::{ self, Format };
+
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CreatureMeta {
+    pub id: CreatureID,
+    pub run: String,
+    pub saved_at: u64,
+    pub world_time: WorldTime,
+
+    pub parent: CreatureID,
+    pub ancestor: CreatureID,
+    pub ancestry: Vec<CreatureID>,
+
+    pub age: WorldTime,
+    pub size: f64,
+    pub spawns: i32,
+    pub eaten: f64,
+    pub descendants: u32,
+}
+
+impl CreatureMeta {
+    pub fn from(creature: &Creature, world: &World, run: &str) -> CreatureMeta
+    {
+        let mut ancestry = vec!();
+        let mut parent = world.lineage.parent(creature.id);
+        while let Some(id) = parent {
+            ancestry.push(id);
+            parent = world.lineage.parent(id);
+        }
+
+        CreatureMeta {
+            id: creature.id,
+            run: run.to_string(),
+            saved_at: unix_time(),
+            world_time: world.time,
+
+            parent: creature.parent,
+            ancestor: creature.ancestor,
+            ancestry: ancestry,
+
+            age: world.time - creature.birthday,
+            size: creature.size,
+            spawns: creature.spawns,
+            eaten: creature.eaten,
+            descendants: world.lineage.descendants(creature.id),
+        }
+    }
+}
+
+/// A saved creature file, which holds the brain and a description of where the creature came from.  Files from before
+/// the library was added contain only the brain, and are loaded with no metadata
+#[derive(Serialize, Deserialize)]
+pub struct SavedCreature {
+    pub meta: Option<CreatureMeta>,
+    pub brain: Brain,
+}
+
+pub struct LibraryEntry {
+    pub path: PathBuf,
+    pub meta: Option<CreatureMeta>,
+}
+
+#[derive(Default)]
+pub struct Query {
+    pub run: Option<String>,
+    pub ancestor: Option<CreatureID>,
+    pub min_spawns: Option<i32>,
+}
+
+impl Query {
+    pub fn matches(&self, entry: &LibraryEntry) -> bool
+    {
+        let meta = match entry.meta {
+            Some(ref meta) => meta,
+            None => return self.run.is_none() && self.ancestor.is_none() && self.min_spawns.is_none(),
+        };
+
+        self.run.as_ref().map_or(true, |run| meta.run == *run)
+            && self.ancestor.map_or(true, |ancestor| meta.ancestor == ancestor || meta.ancestry.contains(&ancestor))
+            && self.min_spawns.map_or(true, |spawns| meta.spawns >= spawns)
+    }
+}
+
+
+/// A directory of saved creatures.  Each run saves into its own subdirectory, and files are never overwritten
+pub struct Library {
+    pub root: PathBuf,
+    pub run: String,
+    pub format: Format,
+}
+
+impl Library {
+    pub fn new(root: &str, format: Format) -> Library
+    {
+        Library {
+            root: PathBuf::from(root),
+            run: format!("run-{}", unix_time()),
+            format: format,
+        }
+    }
+
+    pub fn save(&self, creature: &Creature, world: &World) -> Result<PathBuf, io::Error>
+    {
+        let saved = SavedCreature {
+            meta: Some(CreatureMeta::from(creature, world, &self.run)),
+            brain: creature.brain.clone(),
+        };
+
+        let dir = self.root.join(&self.run);
+        fs::create_dir_all(&dir)?;
+        let path = unique_path(&dir, &creature.id.to_string(), self.format.extension());
+
+        // write_new refuses to replace a file that appeared since the name was chosen
+        format::write_new(&path, &saved)?;
+        return Ok(path);
+    }
+
+    pub fn list(&self) -> Result<Vec<LibraryEntry>, io::Error>
+    {
+        let mut entries = vec!();
+        for path in find_creature_files(&self.root)? {
+            match read_saved(&path) {
+                Ok(saved) => entries.push(LibraryEntry { path: path, meta: saved.meta }),
+                Err(err) => println!("Skipping {}: {}", path.display(), err),
+            }
+        }
+        return Ok(entries);
+    }
+
+    pub fn search(&self, query: &Query) -> Result<Vec<LibraryEntry>, io::Error>
+    {
+        Ok(self.list()?.into_iter().filter(|entry| query.matches(entry)).collect())
+    }
+}
+
+
+pub fn read_saved(path: &Path) -> Result<SavedCreature, io::Error>
+{
+    if Format::from_path(path) != Some(Format::Json) {
+        return format::read(path);
+    }
+
+    let mut buffer = String::new();
+    File::open(path)?.read_to_string(&mut buffer)?;
+
+    let mut value : Value = match serde_json::from_str(&buffer) {
+        Ok(value) => value,
+        Err(err) => return Err(io::Error::new(ErrorKind::InvalidData, format!("error decoding json: {}", err))),
+    };
+    convert_legacy_enums(&mut value);
+
+    if let Ok(saved) = serde_json::from_value::<SavedCreature>(value.clone()) {
+        return Ok(saved);
+    }
+
+    match serde_json::from_value::<Brain>(value) {
+        Ok(brain) => Ok(SavedCreature { meta: None, brain: brain }),
+        Err(err) => Err(io::Error::new(ErrorKind::InvalidData, format!("error decoding json: {}", err))),
+    }
+}
+
+/// Files written with rustc-serialize encode enum variants that have fields as {"variant":"FC","fields":[{...}]},
+/// which is rewritten here into serde's {"FC":{...}} form
+fn convert_legacy_enums(value: &mut Value)
+{
+    let replacement = match *value {
+        Value::Object(ref map) if map.len() == 2 && map.get("variant").map_or(false, |v| v.is_string()) => {
+            match map.get("fields") {
+                Some(&Value::Array(ref fields)) if fields.len() == 1 => {
+                    let mut converted = serde_json::Map::new();
+                    converted.insert(map["variant"].as_str().unwrap().to_string(), fields[0].clone());
+                    Some(Value::Object(converted))
+                },
+                _ => None,
+            }
+        },
+        _ => None,
+    };
+
+    if let Some(replacement) = replacement {
+        *value = replacement;
+    }
+
+    match *value {
+        Value::Object(ref mut map) => for (_, v) in map.iter_mut() { convert_legacy_enums(v) },
+        Value::Array(ref mut list) => for v in list.iter_mut() { convert_legacy_enums(v) },
+        _ => { },
+    }
+}
+
+/// Reads all the creatures in an archive written by a dump
+pub fn read_archive(path: &Path) -> Result<Vec<Creature>, io::Error>
+{
+    format::read(path)
+}
+
+/// Returns all the saved creature files in a directory and its subdirectories, in sorted order
+pub fn find_creature_files(dir: &Path) -> Result<Vec<PathBuf>, io::Error>
+{
+    find_files(dir, format::is_data_file)
+}
+
+/// Returns all the archives of creatures in a directory and its subdirectories, in sorted order
+pub fn find_archives(dir: &Path) -> Result<Vec<PathBuf>, io::Error>
+{
+    find_files(dir, format::is_archive)
+}
+
+fn find_files(dir: &Path, matches: fn(&Path) -> bool) -> Result<Vec<PathBuf>, io::Error>
+{
+    let mut files = vec!();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            files.extend(find_files(&path, matches)?);
+        } else if matches(&path) {
+            files.push(path);
+        }
+    }
+    files.sort();
+    return Ok(files);
+}
+
+/// Returns a path in the directory that doesn't exist yet, by adding a number to the name if needed
+pub fn unique_path(dir: &Path, name: &str, ext: &str) -> PathBuf
+{
+    let mut path = dir.join(format!("{}.{}", name, ext));
+    let mut i = 1;
+    while path.exists() {
+        path = dir.join(format!("{}-{}.{}", name, i, ext));
+        i += 1;
+    }
+    return path;
+}
+
+fn unix_time() -> u64
+{
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::process;
+    use std::io::Write;
+    use super::*;
+
+    #[test]
+    fn legacy_enums_are_converted()
+    {
+        let mut value : Value = serde_json::from_str(r#"{"layers":[{"variant":"FC","fields":[{"W":[[1.0]],"b":[0.5],"activation":"Sigmoid"}]}]}"#).unwrap();
+        convert_legacy_enums(&mut value);
+        let expected : Value = serde_json::from_str(r#"{"layers":[{"FC":{"W":[[1.0]],"b":[0.5],"activation":"Sigmoid"}}]}"#).unwrap();
+        assert_eq!(value, expected);
+    }
+
+    #[test]
+    fn other_objects_are_left_alone()
+    {
+        let original : Value = serde_json::from_str(r#"{"variant":"FC","fields":[1,2],"other":{"variant":3}}"#).unwrap();
+        let mut value = original.clone();
+        convert_legacy_enums(&mut value);
+        assert_eq!(value, original);
+    }
+
+    #[test]
+    fn legacy_brains_are_read_without_metadata()
+    {
+        let path = env::temp_dir().join(format!("legacy-test-{}.json", process::id()));
+        File::create(&path).unwrap().write_all(br#"{"layers":[{"variant":"FC","fields":[{"W":[[1.0,2.0],[3.0,4.0]],"b":[0.5,0.5],"activation":"Tanh"}]}]}"#).unwrap();
+        let saved = read_saved(&path);
+        fs::remove_file(&path).unwrap();
+
+        let saved = saved.unwrap();
+        assert!(saved.meta.is_none());
+        assert_eq!(saved.brain.params(), vec!(1.0, 2.0, 3.0, 4.0, 0.5, 0.5));
+    }
+}
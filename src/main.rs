@@ -1,5 +1,11 @@
 
-extern crate rustc_serialize;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate bincode;
+extern crate rmp_serde;
+extern crate image;
 
 extern crate piston;
 extern crate graphics;
@@ -17,12 +23,117 @@ use opengl_graphics::{ GlGraphics, OpenGL };
 use opengl_graphics::glyph_cache::GlyphCache;
 use graphics::*;
 
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
 
 mod world;
 use world::*;
 
+mod format;
+use format::Format;
+
+mod stats;
+use stats::StatsRecorder;
+
+mod diversity;
+use diversity::Diversity;
+
+mod dump;
+use dump::RankBy;
+
+mod library;
+use library::Library;
+
+mod halloffame;
+use halloffame::HallOfFame;
+
+mod seed;
+mod generator;
+mod mapfile;
+mod resource;
+use resource::Resource;
+mod metabolism;
+mod aging;
+mod action;
+mod plasticity;
+mod population;
+use population::Population;
+mod evolution;
+mod novelty;
+use evolution::Evolution;
+mod environment;
+use environment::Environment;
+mod climate;
+mod vegetation;
+mod scent;
+use scent::{ Levels, SCENT_CHANNELS, SCENT_MAX };
+mod signal;
+
+mod config;
+use config::Config;
+
 
 fn main() {
+    let config = match Config::from_args() {
+        Ok(config) => config,
+        Err(err) => { println!("{}", err); std::process::exit(1); },
+    };
+
+    if config.list_creatures {
+        list_creatures(&config);
+        return;
+    }
+
+    if config.evolve {
+        evolve(&config);
+        return;
+    }
+
+    if config.serve {
+        serve(&config);
+        return;
+    }
+
+    let terrain = match config.map {
+        Some(ref map) => match mapfile::import(Path::new(map), &config.terrain) {
+            Ok(terrain) => terrain,
+            Err(err) => { println!("Error: {}", err); std::process::exit(1); },
+        },
+        None => Terrain::new(&config.terrain),
+    };
+
+    let mut world = match World::new(&config.seeds, terrain, config.rules.clone()) {
+        Ok(world) => world,
+        Err(errors) => {
+            for err in errors {
+                println!("Error: {}", err);
+            }
+            std::process::exit(1);
+        },
+    };
+
+    world.population = match Population::load(&config.population) {
+        Ok(population) => Some(population),
+        Err(errors) => {
+            for err in errors {
+                println!("Error: {}", err);
+            }
+            std::process::exit(1);
+        },
+    };
+
+    // a run that's meant to add to a hall of fame doesn't start unless it can
+    let library = Library::new(&config.library, config.format);
+    let hall = match config.hall_of_fame {
+        Some(ref dir) => match HallOfFame::open(dir, config.hall_size, config.format, &library.run) {
+            Ok(hall) => Some(hall),
+            Err(err) => { println!("Error opening hall of fame {}: {}", dir, err); std::process::exit(1); },
+        },
+        None => None,
+    };
+
     // Change this to OpenGL::V2_1 if not working.
     //let opengl = OpenGL::V3_2;
     let opengl = OpenGL::V2_1;
@@ -38,7 +149,8 @@ fn main() {
 
     let mut gl = GlGraphics::new(opengl);
     let mut glyph = GlyphCache::new("assets/fonts/NotoSans/NotoSans-Regular.ttf").expect("Failed to load font");
-    let mut app = App::new();
+
+    let mut app = App::new(&config, world, library, hall);
 
     let mut mouse_hold = false;
     let mut mouse_pos : [f64; 2] = [0.0, 0.0];
@@ -120,12 +232,12 @@ fn main() {
             },
 
             Event::Input(Press(Keyboard(Key::C))) => {
-		let mut n : Vec<CreatureID> = vec!(0, 0, 0, 0, 0, 0, 0);
+		let mut n : BTreeMap<CreatureID, usize> = BTreeMap::new();
 		for creature in &app.world.creatures {
-	            n[creature.ancestor as usize] += 1;
+	            *n.entry(creature.ancestor).or_insert(0) += 1;
 		}
-                for i in n {
-                    print!("{} ", i);
+                for (ancestor, count) in n {
+                    print!("{}:{} ", ancestor, count);
                 }
                 println!("");
 	    },
@@ -137,8 +249,8 @@ fn main() {
                 if app.viewport.selected > 0 {
                     for creature in &app.world.creatures {
                         if creature.id == app.viewport.selected {
-                            match creature.write(&format!("creatures/{}.json", creature.id)) {
-                                Ok(_) => println!("Saved creature {}", creature.id),
+                            match app.library.save(creature, &app.world) {
+                                Ok(path) => println!("Saved creature {} to {}", creature.id, path.display()),
                                 Err(err) => println!("Error while saving creature {}: {}", creature.id, err),
                             }
                         }
@@ -147,11 +259,24 @@ fn main() {
             },
             Event::Input(Press(Keyboard(Key::D))) => {
                 if app.world.creatures.len() > 0 {
-                    let encoded = rustc_serialize::json::encode(&app.world.creatures[0].brain).unwrap();
-                    //let encoded = rustc_serialize::json::as_pretty_json(&c.brain);
+                    let encoded = serde_json::to_string(&app.world.creatures[0].brain).unwrap();
+                    //let encoded = serde_json::to_string_pretty(&c.brain).unwrap();
                     println!("{}", encoded);
                 }
             },
+            Event::Input(Press(Keyboard(Key::R))) => {
+                match dump::dump_ranked(&app.world, "dumps", app.rank_by, app.format) {
+                    Ok(dir) => println!("Dumped {} creatures ranked by {} to {}", app.world.creatures.len(), app.rank_by.name(), dir),
+                    Err(err) => println!("Error while dumping creatures: {}", err),
+                }
+            },
+            Event::Input(Press(Keyboard(Key::E))) => {
+                let filename = format!("maps/terrain-{}.png", app.world.time);
+                match fs::create_dir_all("maps").and_then(|_| mapfile::export(Path::new(&filename), &app.world.terrain)) {
+                    Ok(_) => println!("Exported terrain to {}", filename),
+                    Err(err) => println!("Error while exporting terrain: {}", err),
+                }
+            },
             Event::Input(Press(Keyboard(Key::I))) => {
                 app.input_on = true;
                 app.input_current = 0;
@@ -177,9 +302,55 @@ fn main() {
 }
 
 
+fn list_creatures(config: &Config)
+{
+    let entries = match Library::new(&config.library, config.format).search(&config.query) {
+        Ok(entries) => entries,
+        Err(err) => { println!("Error reading creature library {}: {}", config.library, err); return; },
+    };
+
+    for entry in entries {
+        match entry.meta {
+            Some(meta) => println!("{}: id {}, run {}, ancestor {}, age {}, spawns {}, descendants {}, eaten/Y {:.4}",
+                entry.path.display(), meta.id, meta.run, meta.ancestor, meta.age, meta.spawns, meta.descendants, meta.eaten / meta.age.max(1) as f64),
+            None => println!("{}: (no metadata)", entry.path.display()),
+        }
+    }
+}
+
+
+fn evolve(config: &Config)
+{
+    let evolution = Evolution {
+        config: &config.evolution,
+        terrain: &config.terrain,
+        map: &config.map,
+        rules: &config.rules,
+        format: config.format,
+    };
+
+    if let Err(err) = evolution.run(&config.seeds) {
+        println!("Error: {}", err);
+        std::process::exit(1);
+    }
+}
+
+
+fn serve(config: &Config)
+{
+    let mut environment = Environment::new(&config.environment, &config.terrain, &config.map, &config.rules, &config.seeds);
+
+    if let Err(err) = environment::serve(&mut environment) {
+        println!("Error: {}", err);
+        std::process::exit(1);
+    }
+}
+
+
 const BORDER_WIDTH : u32 = 20;
 const SIDE_WIDTH : u32 = 250;
 const FONTSIZE : u32 = 20;
+const DIVERSITY_INTERVAL : WorldTime = 100;
 
 
 type ScreenPoint = [u32; 2];
@@ -196,13 +367,20 @@ struct WorldViewport {
 pub struct App {
     world: World,
     viewport: WorldViewport,
+    stats: Option<StatsRecorder>,
+    diversity: Option<Diversity>,
+    species_threshold: f64,
+    rank_by: RankBy,
+    format: Format,
+    library: Library,
+    hall: Option<HallOfFame>,
 
     input_on: bool,
     input_current: i32,
 }
 
 impl App {
-    fn new() -> App
+    fn new(config: &Config, world: World, library: Library, hall: Option<HallOfFame>) -> App
     {
         let viewport = WorldViewport {
             offset: [ BORDER_WIDTH, BORDER_WIDTH ],
@@ -213,9 +391,24 @@ impl App {
             selected: 0,
         };
 
+        let stats = match config.stats_file {
+            Some(ref filename) => match StatsRecorder::new(filename, config.stats_format, config.stats_interval) {
+                Ok(stats) => Some(stats),
+                Err(err) => { println!("Error opening stats file {}: {}", filename, err); None },
+            },
+            None => None,
+        };
+
         return App {
-            world: World::new(),
+            world: world,
             viewport: viewport,
+            stats: stats,
+            diversity: None,
+            species_threshold: config.species_threshold,
+            rank_by: config.rank_by,
+            format: config.format,
+            library: library,
+            hall: hall,
 
             input_on: false,
             input_current: 0,
@@ -231,6 +424,10 @@ impl App {
 
         self.world.render(c, gl, glyph, &self.viewport);
 
+        if let Some(ref diversity) = self.diversity {
+            diversity.render(c, gl, glyph, &self.viewport);
+        }
+
         if self.input_on {
             let transform = c.transform.trans(self.viewport.offset[0] as f64, (self.viewport.size[1] + self.viewport.offset[1] + FONTSIZE) as f64);
             Text::new_color([1.0, 1.0, 1.0, 1.0], FONTSIZE).draw(&format!("> {}", self.input_current), glyph, &c.draw_state, transform, gl);        
@@ -244,6 +441,33 @@ impl App {
 
         self.world.timeslice();
 
+        // diversity is slow to measure, so it's measured once and shared between the side panel and the stats, and isn't
+        // measured again while the time stands still
+        let stats_due = self.stats.as_ref().map_or(false, |stats| stats.due(self.world.time));
+        let measured = self.diversity.as_ref().map_or(false, |diversity| diversity.time == self.world.time);
+        if !measured && (self.world.time % DIVERSITY_INTERVAL == 0 || stats_due || self.diversity.is_none()) {
+            self.diversity = Some(Diversity::measure(&self.world, self.species_threshold));
+        }
+
+        if let (Some(stats), Some(diversity)) = (self.stats.as_mut(), self.diversity.as_ref()) {
+            if let Err(err) = stats.update(&self.world, diversity) {
+                println!("Error writing stats, recording stopped: {}", err);
+                self.stats = None;
+            }
+        }
+
+        if let Some(hall) = self.hall.as_mut() {
+            if hall.due(self.world.time) {
+                match hall.update(&self.world) {
+                    Ok(()) => hall.print(),
+                    Err(err) => {
+                        println!("Error saving hall of fame, saving stopped: {}", err);
+                        self.hall = None;
+                    },
+                }
+            }
+        }
+
         if self.world.time % 1000 == 0 && self.world.creatures.len() > 0 {
             let mut most_spawns = &self.world.creatures[0];
             let mut most_eaten = &self.world.creatures[0];
@@ -319,7 +543,8 @@ impl World {
             &format!("Pop: {}", self.creatures.len()),
             &format!("Total: {}", self.total_lives),
             &format!("Food: {:.0}", self.terrain.total_food()),
-            &format!("Season: {:.4}", self.terrain.season),
+            &format!("Season: {:.4}", self.terrain.climate.season()),
+            &format!("Climate: {}", self.terrain.climate.describe()),
             &format!("Oldest: {} / {}", self.get_oldest(), self.time),
         ];
 
@@ -340,13 +565,34 @@ impl World {
 }
 
 
+impl Diversity {
+    fn render(&self, c: &Context, gl: &mut GlGraphics, glyph: &mut GlyphCache, viewport: &WorldViewport)
+    {
+        let mut lines = vec!(
+            format!("Diversity: {:.2}", self.mean_distance),
+            format!("Species: {}", self.species),
+            format!("From Ancestor: {:.2}", self.mean_ancestor_distance()),
+        );
+        if let Some(distance) = self.ancestor_distance(viewport.selected) {
+            lines.push(format!("Selected From Ancestor: {:.2}", distance));
+        }
+
+        for i in 0..lines.len() {
+            let transform = c.transform.trans((viewport.size[0] + 20) as f64, (viewport.offset[1] + 20 + FONTSIZE * (i + 7) as u32) as f64);
+            Text::new_color([1.0, 1.0, 1.0, 1.0], FONTSIZE).draw(&lines[i], glyph, &c.draw_state, transform, gl);
+        }
+    }
+}
+
+
 impl Terrain {
     fn render(&self, c: &Context, gl: &mut GlGraphics, viewport: &WorldViewport)
     {
         let (mut x, mut y) = (viewport.offset[0], viewport.offset[1]);
         for col in viewport.origin[0] as usize..self.size[0] {
             for row in viewport.origin[1] as usize..self.size[1] {
-                self.tiles[col][row].render(c, gl, x, y, viewport.zoom);
+                let scent = self.scent.as_ref().map_or([0.0; SCENT_CHANNELS], |scent| scent.level([ col as f64, row as f64 ]));
+                self.tiles[col][row].render(c, gl, x, y, viewport.zoom, &self.resources, scent);
                 y += viewport.zoom as u32;
                 if y >= viewport.offset[1] + viewport.size[1] {
                     break;
@@ -360,26 +606,66 @@ impl Terrain {
         }
 
 
-        rectangle([1.0, 0.0, 0.0, 1.0], [ 0.0, 0.0, 20.0, 1.0 ], c.transform.trans(viewport.offset[0] as f64, self.season_height as f64 * viewport.zoom + viewport.offset[1] as f64), gl);
+        if let Some(band) = self.climate.band() {
+            rectangle([1.0, 0.0, 0.0, 1.0], [ 0.0, 0.0, 20.0, 1.0 ], c.transform.trans(viewport.offset[0] as f64, band as f64 * viewport.zoom + viewport.offset[1] as f64), gl);
+        }
     }
 }
 
 
 impl Tile {
-    fn render(&self, c: &Context, gl: &mut GlGraphics, x : u32, y : u32, size : f64)
+    fn render(&self, c: &Context, gl: &mut GlGraphics, x : u32, y : u32, size : f64, resources: &Vec<Resource>, scent: Levels)
     {
         const BLACK : [f32; 4] = [0.0, 0.0, 0.0, 1.0];
         let transform = c.transform.trans(x as f64, y as f64);
         //rectangle(self.colour(), rectangle::square(0.0, 0.0, 20.0), transform, gl);
-        Rectangle::new(self.colour()).border(rectangle::Border { color: BLACK, radius: 1.0 }).draw(rectangle::square(0.0, 0.0, size), &c.draw_state, transform, gl);
+        Rectangle::new(self.add_scent(self.tint(self.colour(resources)), scent)).border(rectangle::Border { color: BLACK, radius: 1.0 }).draw(rectangle::square(0.0, 0.0, size), &c.draw_state, transform, gl);
     }
 
-    fn colour(&self) -> [f32; 4]
+    fn colour(&self, resources: &Vec<Resource>) -> [f32; 4]
     {
+        if self.kind == TileKind::Wall {
+            return [ 0.2, 0.2, 0.6, 1.0 ];
+        }
+
+        // with more than one resource, the first three are shown as red, green and blue
+        if resources.len() > 1 {
+            let mut colour = [ 0.0, 0.0, 0.0, 1.0 ];
+            for (i, resource) in resources.iter().take(3).enumerate() {
+                colour[i] = (self.food[i] / resource.max) as f32;
+            }
+            return colour;
+        }
+        let food = resources.first().map_or(0.0, |resource| self.food[0] / resource.max) as f32;
+
         //return graphics::math::hsv([1.0, 1.0, 1.0, 1.0], self.ttype as f32 / 5.0, 0.75, self.food as f32 / 100.0);
 
         //[ self.food as f32 / 100.0, self.ttype as f32 / 5.0, 0.75, 1.0 ]                          // red food, green type
-        [ self.food as f32 / 100.0, self.food as f32 / 100.0, self.food as f32 / 100.0, 1.0 ]     // black and white food
+        [ food, food, food, 1.0 ]     // black and white food
+    }
+
+    /// Mixes the colour of the food with the colour of water or hazards, so both can be seen
+    fn tint(&self, colour: [f32; 4]) -> [f32; 4]
+    {
+        let tint = match self.kind {
+            TileKind::Water => [ 0.1, 0.3, 0.9 ],
+            TileKind::Hazard => [ 0.9, 0.2, 0.1 ],
+            _ => return colour,
+        };
+        [ (colour[0] + tint[0]) / 2.0, (colour[1] + tint[1]) / 2.0, (colour[2] + tint[2]) / 2.0, 1.0 ]
+    }
+
+    /// Brightens the colour with the scent on the tile, where the first channel is magenta and the second is yellow
+    fn add_scent(&self, mut colour: [f32; 4], scent: Levels) -> [f32; 4]
+    {
+        const SCENT_COLOURS : [[f32; 3]; 2] = [ [ 1.0, 0.0, 1.0 ], [ 1.0, 1.0, 0.0 ] ];
+        for channel in 0..SCENT_CHANNELS.min(SCENT_COLOURS.len()) {
+            let strength = (scent[channel] / SCENT_MAX) as f32;
+            for i in 0..3 {
+                colour[i] = (colour[i] + SCENT_COLOURS[channel][i] * strength).min(1.0);
+            }
+        }
+        return colour;
     }
 }
 
@@ -400,6 +686,10 @@ impl Creature {
         if self.id == viewport.selected {
             ellipse([1.0, 0.0, 0.0, 1.0], rectangle::centered_square(0.0, 0.0, size / 2.0 + 2.0), transform, gl);
             self.render_info(c, gl, glyph, viewport, time);
+        } else if self.signal.iter().any(|s| s.abs() > 0.1) {
+            // a halo coloured by the signal the creature is making, with the first channel as red and the second as blue
+            let halo = [ ((self.signal[0] + 1.0) / 2.0) as f32, 0.2, ((self.signal[1] + 1.0) / 2.0) as f32, 0.8 ];
+            ellipse(halo, rectangle::centered_square(0.0, 0.0, size / 2.0 + 2.0), transform, gl);
         }
 
         ellipse(colour, rectangle::centered_square(0.0, 0.0, size / 2.0), transform, gl);
@@ -416,6 +706,11 @@ impl Creature {
             &format!("Spawns: {}", self.spawns),
             &format!("Eaten: {:.2}", self.eaten),
             &format!("Eaten/Y: {:.2}", self.eaten / (time - self.birthday) as f64),
+            &format!("Digestion: {}", self.digestion.iter().map(|d| format!("{:.2}", d)).collect::<Vec<String>>().join(" ")),
+            &format!("Energy: {:.2} ({} reactions)", self.metabolism.energy, self.metabolism.reactions.len()),
+            &format!("Signal: {}", self.signal.iter().map(|s| format!("{:.2}", s)).collect::<Vec<String>>().join(" ")),
+            &format!("Longevity: {:.2}", self.longevity),
+            &format!("Investment: {:.2}", self.investment),
         ];
 
         for i in 0..lines.len() {
@@ -426,8 +721,8 @@ impl Creature {
 
     fn print_info(&self, time: WorldTime)
     {
-        let encoded = rustc_serialize::json::encode(&self.brain).unwrap();
-        //let encoded = rustc_serialize::json::as_pretty_json(&self.world.creatures[0].brain);
+        let encoded = serde_json::to_string(&self.brain).unwrap();
+        //let encoded = serde_json::to_string_pretty(&self.world.creatures[0].brain).unwrap();
         println!("id: {}", self.id);
         println!("ancestor: {}", self.ancestor);
         println!("age: {}", time - self.birthday);
@@ -0,0 +1,244 @@
+
+use std::fs::File;
+use std::io::{ self, Read, Write, BufWriter, ErrorKind };
+use std::path::Path;
+
+use image::{ self, DynamicImage, GenericImage, ImageBuffer, Rgb };
+
+use world::*;
+use generator::{ TerrainConfig, MAX_TTYPE };
+use resource::MAX_RESOURCES;
+
+
+/// Loads a terrain from an image.  Greyscale images (including PGM files) are heightmaps, where the brightness sets the
+/// tile type.  In colour images, the green channel sets the tile type, the red channel sets the initial amount of the first
+/// resource, and the blue channel sets the kind of tile (see `to_kind`).  The image is stretched to fit the size of the
+/// terrain
+pub fn import(path: &Path, config: &TerrainConfig) -> Result<Terrain, io::Error>
+{
+    let image = if is_pgm(path) {
+        read_pgm(path)?
+    } else {
+        match image::open(path) {
+            Ok(image) => image,
+            Err(err) => return Err(io::Error::new(ErrorKind::InvalidData, format!("error loading {}: {}", path.display(), err))),
+        }
+    };
+
+    let greyscale = match image {
+        DynamicImage::ImageLuma8(_) | DynamicImage::ImageLumaA8(_) => true,
+        _ => false,
+    };
+
+    let (width, height) = image.dimensions();
+    let rgb = image.to_rgb();
+
+    let resources = &config.resources;
+    let mut terrain = Terrain::empty(config);
+    for col in 0..terrain.size[0] {
+        for row in 0..terrain.size[1] {
+            let x = (col as u32 * width / terrain.size[0] as u32).min(width - 1);
+            let y = (row as u32 * height / terrain.size[1] as u32).min(height - 1);
+            let pixel = rgb.get_pixel(x, y).data;
+
+            let tile = &mut terrain.tiles[col][row];
+            if greyscale {
+                *tile = Tile::new(to_ttype(pixel[0]), resources);
+            } else {
+                *tile = Tile::new(to_ttype(pixel[1]), resources);
+                if let Some(resource) = resources.first() {
+                    tile.food[0] = pixel[0] as f64 / 255.0 * resource.max;
+                }
+                tile.kind = to_kind(pixel[2]);
+                if tile.kind == TileKind::Wall {
+                    tile.food = [0.0; MAX_RESOURCES];
+                }
+            }
+        }
+    }
+    return Ok(terrain);
+}
+
+/// Saves the terrain as an image, in the same encoding used by import.  PGM files are saved as a heightmap of the tile types,
+/// and any other type of file is saved as a colour image with the first resource and the kinds of tiles
+pub fn export(path: &Path, terrain: &Terrain) -> Result<(), io::Error>
+{
+    if is_pgm(path) {
+        let mut data = vec!();
+        for row in 0..terrain.size[1] {
+            for col in 0..terrain.size[0] {
+                data.push(from_ttype(terrain.tiles[col][row].ttype));
+            }
+        }
+        return write_pgm(path, terrain.size[0], terrain.size[1], &data);
+    }
+
+    let image = ImageBuffer::from_fn(terrain.size[0] as u32, terrain.size[1] as u32, |x, y| {
+        let tile = &terrain.tiles[x as usize][y as usize];
+        let food = terrain.resources.first().map_or(0.0, |resource| tile.food[0] / resource.max);
+        Rgb([ (food * 255.0) as u8, from_ttype(tile.ttype), from_kind(tile.kind) ])
+    });
+    return image.save(path);
+}
+
+fn to_ttype(value: u8) -> i32
+{
+    (value as i32 * (MAX_TTYPE + 1) / 256).min(MAX_TTYPE)
+}
+
+fn from_ttype(ttype: i32) -> u8
+{
+    // the middle of the range of values that map back to the same tile type
+    ((ttype as f64 + 0.5) * 256.0 / (MAX_TTYPE + 1) as f64) as u8
+}
+
+/// Converts a blue value into the kind of tile closest to it, where ground is 0, water is 85, hazards are 170 and walls
+/// are 255
+fn to_kind(value: u8) -> TileKind
+{
+    match value {
+        0..=42 => TileKind::Ground,
+        43..=127 => TileKind::Water,
+        128..=212 => TileKind::Hazard,
+        _ => TileKind::Wall,
+    }
+}
+
+fn from_kind(kind: TileKind) -> u8
+{
+    match kind {
+        TileKind::Ground => 0,
+        TileKind::Water => 85,
+        TileKind::Hazard => 170,
+        TileKind::Wall => 255,
+    }
+}
+
+
+fn is_pgm(path: &Path) -> bool
+{
+    path.extension().map_or(false, |ext| ext == "pgm")
+}
+
+/// Reads a binary (P5) or text (P2) PGM file
+fn read_pgm(path: &Path) -> Result<DynamicImage, io::Error>
+{
+    let mut data = vec!();
+    File::open(path)?.read_to_end(&mut data)?;
+
+    parse_pgm(&data).map_err(|msg| io::Error::new(ErrorKind::InvalidData, format!("error loading {}: {}", path.display(), msg)))
+}
+
+fn parse_pgm(data: &[u8]) -> Result<DynamicImage, String>
+{
+    // the header is four whitespace separated fields (ignoring comments), followed by a single whitespace character
+    let mut fields : Vec<String> = vec!();
+    let mut i = 0;
+    while fields.len() < 4 && i < data.len() {
+        if data[i] == b'#' {
+            while i < data.len() && data[i] != b'\n' {
+                i += 1;
+            }
+        } else if (data[i] as char).is_whitespace() {
+            i += 1;
+        } else {
+            let start = i;
+            while i < data.len() && !(data[i] as char).is_whitespace() {
+                i += 1;
+            }
+            fields.push(String::from_utf8_lossy(&data[start..i]).into_owned());
+        }
+    }
+    i += 1;
+
+    if fields.len() < 4 || (fields[0] != "P5" && fields[0] != "P2") {
+        return Err(String::from("not a PGM file"));
+    }
+
+    let width : u32 = fields[1].parse().map_err(|_| String::from("invalid width"))?;
+    let height : u32 = fields[2].parse().map_err(|_| String::from("invalid height"))?;
+    let maxval : u32 = fields[3].parse().map_err(|_| String::from("invalid maximum value"))?;
+    if width == 0 || height == 0 || maxval == 0 || maxval > 255 {
+        return Err(String::from("unsupported image size or maximum value"));
+    }
+
+    let count = match (width as usize).checked_mul(height as usize) {
+        Some(count) => count,
+        None => return Err(String::from("image is too large")),
+    };
+
+    let values : Vec<u32> = if fields[0] == "P5" {
+        data.iter().skip(i).take(count).map(|v| *v as u32).collect()
+    } else {
+        let text = String::from_utf8_lossy(&data[i.min(data.len())..]).into_owned();
+        let mut values = vec!();
+        for v in text.split_whitespace().take(count) {
+            values.push(v.parse().map_err(|_| format!("invalid pixel value {}", v))?);
+        }
+        values
+    };
+
+    if values.len() < count {
+        return Err(String::from("not enough pixel data"));
+    }
+
+    // values above the maximum are treated as the maximum, which keeps the scaling from overflowing
+    let pixels = values.iter().map(|&v| (v.min(maxval) * 255 / maxval) as u8).collect();
+    match ImageBuffer::from_raw(width, height, pixels) {
+        Some(buffer) => Ok(DynamicImage::ImageLuma8(buffer)),
+        None => Err(String::from("not enough pixel data")),
+    }
+}
+
+fn write_pgm(path: &Path, width: usize, height: usize, data: &Vec<u8>) -> Result<(), io::Error>
+{
+    let mut f = BufWriter::new(File::create(path)?);
+    write!(f, "P5\n{} {}\n255\n", width, height)?;
+    f.write_all(data)?;
+    return f.flush();
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pixels(data: &[u8]) -> Vec<u8>
+    {
+        match parse_pgm(data) {
+            Ok(DynamicImage::ImageLuma8(buffer)) => buffer.into_raw(),
+            Ok(_) => panic!("not a greyscale image"),
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    #[test]
+    fn binary_pgm()
+    {
+        assert_eq!(pixels(b"P5\n# a comment\n2 2\n255\n\x00\x40\x80\xff"), vec!(0, 64, 128, 255));
+    }
+
+    #[test]
+    fn text_pgm_is_scaled_to_the_maximum()
+    {
+        assert_eq!(pixels(b"P2 3 1 15\n0 15 5\n"), vec!(0, 255, 85));
+    }
+
+    #[test]
+    fn values_above_the_maximum_are_clamped()
+    {
+        assert_eq!(pixels(b"P2 2 1 10\n10 4000000000\n"), vec!(255, 255));
+        assert_eq!(pixels(b"P5 2 1 10\n\x05\xff"), vec!(127, 255));
+    }
+
+    #[test]
+    fn invalid_pgm_files_are_errors()
+    {
+        assert!(parse_pgm(b"P2 2 1 255\n1 junk\n").is_err());
+        assert!(parse_pgm(b"P2 2 2 255\n1 2 3\n").is_err());
+        assert!(parse_pgm(b"P5 4294967295 4294967295 255\n\x00").is_err());
+        assert!(parse_pgm(b"P5 2 2 65535\n").is_err());
+        assert!(parse_pgm(b"P6 1 1 255\n\x00").is_err());
+        assert!(parse_pgm(b"").is_err());
+    }
+}
@@ -0,0 +1,199 @@
+
+extern crate rand;
+use self::rand::Rng;
+
+use resource::{ Amounts, MAX_RESOURCES };
+
+
+const MAX_REACTIONS : usize = 4;
+const MAX_DEPTH : usize = 4;
+
+/// The most energy a reaction can make from each unit of resource, so that formulas can't make energy out of nothing
+const MAX_YIELD : f64 = 1.0;
+/// The energy cost per tick of each node in a reaction's formula, for upkeep of the machinery
+const NODE_COST : f64 = 0.002;
+
+/// The energy cost per tick of being alive, which is multiplied by `size^2 * (1 + size)`.  This is the cost of living
+/// without a metabolism converted into energy, so a creature with perfect reactions breaks even on the same food as one
+/// without a metabolism.  Costs rise faster than a creature can eat, so above a size of 1.0 it needs more than one unit
+/// of food per tick, and creatures settle at the size their food supports instead of growing without limit
+const LIVING_COST : f64 = 0.5;
+/// The energy cost per tick of moving at full speed, for each unit of size squared
+const MOVING_COST : f64 = 0.2;
+/// How much energy a creature can store for each unit of size, before the surplus is turned into growth
+const RESERVE : f64 = 10.0;
+/// How much of each resource can be stored before it's converted, with any more being wasted
+const STORE_LIMIT : f64 = 20.0;
+
+
+/// A formula that converts an amount of a resource into energy
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Expr {
+    Amount,
+    Const(f64),
+    Add(Box<Expr>, Box<Expr>),
+    Mul(Box<Expr>, Box<Expr>),
+    Min(Box<Expr>, Box<Expr>),
+    Sqrt(Box<Expr>),
+}
+
+impl Expr {
+    fn random(depth: usize) -> Expr
+    {
+        let choice = if depth >= MAX_DEPTH { rand::thread_rng().gen_range(0, 2) } else { rand::thread_rng().gen_range(0, 6) };
+        match choice {
+            0 => Expr::Amount,
+            1 => Expr::Const(rand::thread_rng().gen_range(0.0, 1.0)),
+            2 => Expr::Add(Box::new(Expr::random(depth + 1)), Box::new(Expr::random(depth + 1))),
+            3 => Expr::Mul(Box::new(Expr::random(depth + 1)), Box::new(Expr::random(depth + 1))),
+            4 => Expr::Min(Box::new(Expr::random(depth + 1)), Box::new(Expr::random(depth + 1))),
+            _ => Expr::Sqrt(Box::new(Expr::random(depth + 1))),
+        }
+    }
+
+    pub fn eval(&self, amount: f64) -> f64
+    {
+        match *self {
+            Expr::Amount => amount,
+            Expr::Const(value) => value,
+            Expr::Add(ref a, ref b) => a.eval(amount) + b.eval(amount),
+            Expr::Mul(ref a, ref b) => a.eval(amount) * b.eval(amount),
+            Expr::Min(ref a, ref b) => a.eval(amount).min(b.eval(amount)),
+            Expr::Sqrt(ref a) => a.eval(amount).max(0.0).sqrt(),
+        }
+    }
+
+    pub fn nodes(&self) -> usize
+    {
+        match *self {
+            Expr::Amount | Expr::Const(_) => 1,
+            Expr::Add(ref a, ref b) | Expr::Mul(ref a, ref b) | Expr::Min(ref a, ref b) => 1 + a.nodes() + b.nodes(),
+            Expr::Sqrt(ref a) => 1 + a.nodes(),
+        }
+    }
+
+    fn mutate(&self, depth: usize) -> Expr
+    {
+        // occasionally replace a whole branch, otherwise nudge the constants
+        if rand::thread_rng().gen_range(0.0, 1.0) < 0.02 {
+            return Expr::random(depth);
+        }
+
+        match *self {
+            Expr::Amount => Expr::Amount,
+            Expr::Const(value) => Expr::Const((value + rand::thread_rng().gen_range(-0.05, 0.05)).max(0.0)),
+            Expr::Add(ref a, ref b) => Expr::Add(Box::new(a.mutate(depth + 1)), Box::new(b.mutate(depth + 1))),
+            Expr::Mul(ref a, ref b) => Expr::Mul(Box::new(a.mutate(depth + 1)), Box::new(b.mutate(depth + 1))),
+            Expr::Min(ref a, ref b) => Expr::Min(Box::new(a.mutate(depth + 1)), Box::new(b.mutate(depth + 1))),
+            Expr::Sqrt(ref a) => Expr::Sqrt(Box::new(a.mutate(depth + 1))),
+        }
+    }
+}
+
+
+/// Converts up to `rate` of one stored resource into energy each tick, using a formula
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Reaction {
+    pub input: usize,
+    pub rate: f64,
+    pub formula: Expr,
+}
+
+impl Reaction {
+    fn random(resources: usize) -> Reaction
+    {
+        Reaction {
+            input: rand::thread_rng().gen_range(0, resources.max(1)),
+            rate: rand::thread_rng().gen_range(0.5, 2.0),
+            // start with a simple linear conversion, which evolution can then build on
+            formula: Expr::Mul(Box::new(Expr::Const(rand::thread_rng().gen_range(0.7, 1.0))), Box::new(Expr::Amount)),
+        }
+    }
+
+    /// The energy made from an amount of the input resource, minus the upkeep of the formula
+    pub fn react(&self, amount: f64) -> f64
+    {
+        self.formula.eval(amount).max(0.0).min(amount * MAX_YIELD) - self.formula.nodes() as f64 * NODE_COST
+    }
+}
+
+
+/// The set of reactions that a creature uses to turn the resources it eats into energy
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Metabolism {
+    pub reactions: Vec<Reaction>,
+    pub stores: Amounts,
+    pub energy: f64,
+}
+
+impl Metabolism {
+    pub fn new(resources: usize) -> Metabolism
+    {
+        let count = rand::thread_rng().gen_range(1, 3);
+        Metabolism {
+            reactions: (0..count).map(|_| Reaction::random(resources)).collect(),
+            stores: [0.0; MAX_RESOURCES],
+            // enough to move around and find food before the first meal
+            energy: RESERVE / 2.0,
+        }
+    }
+
+    pub fn spawn(&self, resources: usize) -> Metabolism
+    {
+        let mut reactions : Vec<Reaction> = self.reactions.iter().map(|reaction| {
+            Reaction {
+                input: if rand::thread_rng().gen_range(0.0, 1.0) < 0.01 { rand::thread_rng().gen_range(0, resources.max(1)) } else { reaction.input },
+                rate: (reaction.rate + rand::thread_rng().gen_range(-0.05, 0.05)).max(0.0),
+                formula: reaction.formula.mutate(0),
+            }
+        }).collect();
+
+        let change = rand::thread_rng().gen_range(0.0, 1.0);
+        if change < 0.01 && reactions.len() < MAX_REACTIONS {
+            reactions.push(Reaction::random(resources));
+        } else if change > 0.99 && reactions.len() > 1 {
+            let index = rand::thread_rng().gen_range(0, reactions.len());
+            reactions.remove(index);
+        }
+
+        Metabolism {
+            reactions: reactions,
+            stores: [0.0; MAX_RESOURCES],
+            energy: 0.0,
+        }
+    }
+
+    /// Stores the eaten resources, runs the reactions, and pays for living and moving, where the cost of living is multiplied
+    /// by the given effort for old age and for the action taken.  Returns the change in size, which is positive when there's
+    /// more energy than can be stored, and negative when there isn't enough to pay the costs
+    pub fn timeslice(&mut self, eaten: &Amounts, size: f64, speed: f64, effort: f64) -> f64
+    {
+        for i in 0..MAX_RESOURCES {
+            self.stores[i] = (self.stores[i] + eaten[i]).min(STORE_LIMIT);
+        }
+
+        for reaction in &self.reactions {
+            let input = reaction.input.min(MAX_RESOURCES - 1);
+            let amount = self.stores[input].min(reaction.rate);
+            self.stores[input] -= amount;
+            self.energy += reaction.react(amount);
+        }
+
+        self.energy -= LIVING_COST * size.powf(2.0) * (1.0 + size) * effort;
+        self.energy -= MOVING_COST * speed * size.powf(2.0);
+
+        // the same conversion between food and size as without a metabolism
+        let reserve = RESERVE * size;
+        if self.energy > reserve {
+            let surplus = self.energy - reserve;
+            self.energy = reserve;
+            return (1.0 / size).powf(2.0) * surplus * 0.01;
+        } else if self.energy < 0.0 {
+            let deficit = self.energy;
+            self.energy = 0.0;
+            return (1.0 / size).powf(2.0) * deficit * 0.01;
+        }
+        return 0.0;
+    }
+}
+
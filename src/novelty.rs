@@ -0,0 +1,124 @@
+
+use world::{ WorldPoint, WorldTime };
+
+
+/// The number of columns and rows in the coarse grid that visits to the terrain are counted in
+const GRID : [usize; 2] = [ 8, 4 ];
+
+/// The speed and turn that count as full speed and a full turn in a descriptor
+const FULL_SPEED : f64 = 0.2;
+const FULL_TURN : f64 = 0.2;
+
+/// How often the novelty of the living creatures is measured, when it's used as a bonus to reproduction
+pub const NOVELTY_INTERVAL : WorldTime = 1000;
+
+
+/// A record of how a creature has behaved over its life, which can be turned into a descriptor for comparing behaviours
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Behaviour {
+    pub ticks: u64,
+    /// The number of ticks spent in each cell of a coarse grid over the terrain
+    pub visits: Vec<u64>,
+    pub total_speed: f64,
+    pub total_turn: f64,
+    /// The number of ticks spent on tiles with plenty of food
+    pub feeding: u64,
+}
+
+impl Default for Behaviour {
+    fn default() -> Behaviour
+    {
+        Behaviour {
+            ticks: 0,
+            visits: vec![0; GRID[0] * GRID[1]],
+            total_speed: 0.0,
+            total_turn: 0.0,
+            feeding: 0,
+        }
+    }
+}
+
+impl Behaviour {
+    pub fn record(&mut self, position: WorldPoint, size: [usize; 2], speed: f64, turn: f64, rich: bool)
+    {
+        let col = (position[0] / size[0] as f64 * GRID[0] as f64) as usize;
+        let row = (position[1] / size[1] as f64 * GRID[1] as f64) as usize;
+        if self.visits.len() != GRID[0] * GRID[1] {
+            self.visits = vec![0; GRID[0] * GRID[1]];
+        }
+
+        self.ticks += 1;
+        self.visits[col.min(GRID[0] - 1) * GRID[1] + row.min(GRID[1] - 1)] += 1;
+        self.total_speed += speed;
+        self.total_turn += turn.abs();
+        if rich {
+            self.feeding += 1;
+        }
+    }
+
+    /// The fraction of time spent in each cell of the grid, followed by the mean speed, the mean turning rate, and the
+    /// fraction of time spent on tiles with plenty of food, each between 0.0 and 1.0
+    pub fn descriptor(&self) -> Vec<f64>
+    {
+        let ticks = self.ticks.max(1) as f64;
+        let mut descriptor : Vec<f64> = self.visits.iter().map(|&visits| visits as f64 / ticks).collect();
+        descriptor.push((self.total_speed / ticks / FULL_SPEED).min(1.0));
+        descriptor.push((self.total_turn / ticks / FULL_TURN).min(1.0));
+        descriptor.push(self.feeding as f64 / ticks);
+        return descriptor;
+    }
+}
+
+
+/// The behaviours that have been novel enough to remember, which the novelty of new behaviours is measured against
+pub struct Archive {
+    pub descriptors: Vec<Vec<f64>>,
+    /// The number of nearest neighbours that novelty is averaged over
+    pub neighbours: usize,
+    /// How novel a behaviour has to be to be added to the archive
+    pub threshold: f64,
+    /// The most behaviours to remember, after which the oldest are forgotten
+    pub limit: usize,
+}
+
+impl Archive {
+    pub fn new() -> Archive
+    {
+        Archive {
+            descriptors: vec!(),
+            neighbours: 15,
+            threshold: 0.5,
+            limit: 1000,
+        }
+    }
+
+    /// Measures the novelty of each of a group of behaviours, against each other and the archive, and then adds the ones
+    /// that are novel enough to the archive
+    pub fn measure(&mut self, descriptors: &Vec<Vec<f64>>) -> Vec<f64>
+    {
+        let novelty : Vec<f64> = descriptors.iter().enumerate().map(|(i, descriptor)| {
+            let others = descriptors.iter().enumerate().filter(|&(j, _)| j != i).map(|(_, other)| other);
+            let mut distances : Vec<f64> = others.chain(self.descriptors.iter()).map(|other| distance(descriptor, other)).collect();
+            distances.sort_by(|a, b| a.total_cmp(b));
+            distances.truncate(self.neighbours);
+            if distances.len() > 0 { distances.iter().sum::<f64>() / distances.len() as f64 } else { 0.0 }
+        }).collect();
+
+        for (descriptor, &score) in descriptors.iter().zip(novelty.iter()) {
+            if score > self.threshold {
+                self.descriptors.push(descriptor.clone());
+            }
+        }
+        if self.descriptors.len() > self.limit {
+            let excess = self.descriptors.len() - self.limit;
+            self.descriptors.drain(0..excess);
+        }
+        return novelty;
+    }
+}
+
+fn distance(a: &Vec<f64>, b: &Vec<f64>) -> f64
+{
+    a.iter().zip(b.iter()).map(|(x, y)| (x - y).powf(2.0)).sum::<f64>().sqrt()
+}
+
@@ -0,0 +1,58 @@
+
+extern crate rand;
+use self::rand::Rng;
+
+
+/// The most that a learning rate can evolve to
+pub const MAX_RATE : f64 = 0.01;
+
+/// How quickly the food a creature expects to eat on each tick catches up with what it actually eats
+pub const BASELINE_RATE : f64 = 0.01;
+
+
+/// Lets brains change during a creature's life, on top of the weights inherited from its parent.  What's learned isn't
+/// passed on to children, so learning can only guide evolution through the creatures that survive because of it
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Plasticity {
+    /// Connections between neurons that are active together are strengthened on every tick, using Oja's rule so that the
+    /// weights don't grow without bound
+    Hebbian,
+    /// Connections between neurons that were active together are strengthened when the creature eats more than it
+    /// expected to, and weakened when it eats less
+    Reward,
+}
+
+impl Plasticity {
+    pub fn from_str(name: &str) -> Option<Plasticity>
+    {
+        match name {
+            "hebbian" | "oja" => Some(Plasticity::Hebbian),
+            "reward" => Some(Plasticity::Reward),
+            _ => None,
+        }
+    }
+}
+
+/// The change in a weight from one tick of Oja's rule
+pub fn hebbian(rate: f64, input: f64, output: f64, weight: f64) -> f64
+{
+    rate * output * (input - output * weight)
+}
+
+/// The change in a weight from the difference between the food eaten and the food expected
+pub fn reinforce(rate: f64, input: f64, output: f64, reward: f64) -> f64
+{
+    rate * reward * input * output
+}
+
+/// A learning rate for a new brain, which starts out small
+pub fn random_rate() -> f64
+{
+    rand::thread_rng().gen_range(0.0, MAX_RATE / 10.0)
+}
+
+/// The learning rate of a child, which is changed slightly from its parent's
+pub fn mutate_rate(rate: f64) -> f64
+{
+    (rate + rand::thread_rng().gen_range(-0.4 as f64, 0.4 as f64).powf(3.0) * MAX_RATE).max(0.0).min(MAX_RATE)
+}
@@ -0,0 +1,136 @@
+
+extern crate rand;
+use self::rand::Rng;
+
+use world::{ World, Creature, Brain, WorldTime };
+use seed::{ self, SeedError };
+use stats;
+
+
+/// Where new creatures come from when the population is topped up
+pub enum Source {
+    /// Creatures with new random brains
+    Random,
+    /// Mutated copies of saved brains
+    Saved(Vec<Brain>),
+}
+
+impl Source {
+    /// Loads the brains in a file or directory, or uses random brains if no path is given
+    pub fn load(path: &Option<String>) -> Result<Source, Vec<SeedError>>
+    {
+        match *path {
+            Some(ref path) => {
+                let mut errors = vec!();
+                match seed::load_brains(path, &mut errors) {
+                    Some(ref brains) if errors.len() <= 0 => Ok(Source::Saved(brains.clone())),
+                    _ => Err(errors),
+                }
+            },
+            None => Ok(Source::Random),
+        }
+    }
+
+    /// Makes a new creature, with a random colour, which starts its own lineage
+    fn generate(&self, world: &World) -> Creature
+    {
+        let colour = rand::thread_rng().gen_range(0.0, 1.0);
+        let brain = match *self {
+            Source::Random => None,
+            Source::Saved(ref brains) => Some(brains[rand::thread_rng().gen_range(0, brains.len())].spawn()),
+        };
+        Creature::generate(&world.terrain, world.time, colour, -1, brain)
+    }
+}
+
+
+/// Saved creatures which are added to the world at regular intervals
+pub struct Immigration {
+    pub source: Source,
+    pub count: usize,
+    pub interval: WorldTime,
+}
+
+/// The settings for population control, which are given on the command line and loaded into a `Population`
+pub struct PopulationConfig {
+    pub minimum: Option<usize>,
+    pub maximum: Option<usize>,
+    /// The saved creatures used to top up the population, instead of random brains
+    pub reseed: Option<String>,
+    /// The saved creatures, count and interval for immigration
+    pub immigration: Option<(String, usize, WorldTime)>,
+}
+
+impl PopulationConfig {
+    pub fn new() -> PopulationConfig
+    {
+        PopulationConfig {
+            minimum: None,
+            maximum: None,
+            reseed: None,
+            immigration: None,
+        }
+    }
+}
+
+
+/// Keeps the population within limits, so that long runs neither die out nor grow without bound
+pub struct Population {
+    /// When there are fewer creatures than this, new ones are added to make up the difference
+    pub minimum: Option<usize>,
+    /// When there are more creatures than this, the smallest ones are culled
+    pub maximum: Option<usize>,
+    pub reseed: Source,
+    pub immigration: Option<Immigration>,
+}
+
+impl Population {
+    pub fn load(config: &PopulationConfig) -> Result<Population, Vec<SeedError>>
+    {
+        let immigration = match config.immigration {
+            Some((ref path, count, interval)) => Some(Immigration {
+                source: Source::load(&Some(path.clone()))?,
+                count: count,
+                interval: interval.max(1),
+            }),
+            None => None,
+        };
+
+        Ok(Population {
+            minimum: config.minimum,
+            maximum: config.maximum,
+            reseed: Source::load(&config.reseed)?,
+            immigration: immigration,
+        })
+    }
+
+    pub fn timeslice(&self, world: &mut World)
+    {
+        if let Some(ref immigration) = self.immigration {
+            if world.time % immigration.interval == 0 {
+                for _ in 0..immigration.count {
+                    let creature = immigration.source.generate(world);
+                    world.arrive(creature);
+                }
+            }
+        }
+
+        if let Some(minimum) = self.minimum {
+            while world.creatures.len() < minimum {
+                let creature = self.reseed.generate(world);
+                world.arrive(creature);
+            }
+        }
+
+        if let Some(maximum) = self.maximum {
+            if world.creatures.len() > maximum {
+                world.creatures.sort_by(|a, b| stats::highest_first(a.size, b.size));
+                world.deaths += world.creatures.len() - maximum;
+                world.creatures.truncate(maximum);
+                // keep the creatures in order of birth, so that the oldest is still first
+                world.creatures.sort_by_key(|creature| creature.id);
+            }
+        }
+    }
+}
+
@@ -0,0 +1,139 @@
+
+extern crate rand;
+use self::rand::Rng;
+
+
+pub const MAX_RESOURCES : usize = 4;
+
+pub type Amounts = [f64; MAX_RESOURCES];
+
+/// A type of food that grows on the tiles.  Each step of growth adds a random amount up to `rate`, scaled by the season and
+/// by the tile's type raised to `power`.  When `inverse` is set, the least fertile tiles are treated as the most fertile
+#[derive(Clone, Debug)]
+pub struct Resource {
+    pub name: String,
+    pub rate: f64,
+    pub max: f64,
+    pub power: f64,
+    pub inverse: bool,
+}
+
+impl Resource {
+    pub fn new(name: &str, rate: f64, max: f64, power: f64, inverse: bool) -> Resource
+    {
+        Resource {
+            name: name.to_string(),
+            rate: rate,
+            max: max,
+            power: power,
+            inverse: inverse,
+        }
+    }
+
+    /// The single resource that every tile had before multiple resources were added
+    pub fn food() -> Resource
+    {
+        Resource::new("food", 0.20, 100.0, 2.0, false)
+    }
+
+    /// Parses a resource description of the form name:rate:max:power[:inverse]
+    pub fn from_str(desc: &str) -> Result<Resource, String>
+    {
+        let parts : Vec<&str> = desc.split(':').collect();
+        if parts.len() < 4 || parts.len() > 5 || (parts.len() == 5 && parts[4] != "inverse") {
+            return Err(format!("invalid resource {}, expected name:rate:max:power[:inverse]", desc));
+        }
+
+        let number = |s: &str| match s.parse::<f64>() {
+            Ok(value) if value.is_finite() => Ok(value),
+            _ => Err(format!("invalid number {} in resource {}", s, desc)),
+        };
+        let (rate, max, power) = (number(parts[1])?, number(parts[2])?, number(parts[3])?);
+        if rate < 0.0 {
+            return Err(format!("invalid resource {}, the rate can't be negative", desc));
+        }
+        if max <= 0.0 {
+            return Err(format!("invalid resource {}, the maximum has to be more than zero", desc));
+        }
+        if power < 0.0 {
+            return Err(format!("invalid resource {}, the power can't be negative", desc));
+        }
+        Ok(Resource::new(parts[0], rate, max, power, parts.len() == 5))
+    }
+
+    pub fn growth(&self, ttype: i32, max_ttype: i32, season: f64) -> f64
+    {
+        let fertility = if self.inverse { max_ttype - ttype } else { ttype };
+        rand::thread_rng().gen_range(0.0, self.rate.max(f64::EPSILON)) * season * (fertility as f64).powf(self.power)
+    }
+
+    /// How well the resource grows on a tile type, from 0.0 on the least fertile tiles to 1.0 on the most fertile
+    pub fn fertility(&self, ttype: i32, max_ttype: i32) -> f64
+    {
+        let fertility = if self.inverse { max_ttype - ttype } else { ttype };
+        (fertility as f64 / max_ttype as f64).max(0.0).powf(self.power)
+    }
+}
+
+
+/// Returns random digestion efficiencies for the given number of resources, which add up to 1.0
+pub fn random_digestion(count: usize) -> Amounts
+{
+    let mut digestion = [0.0; MAX_RESOURCES];
+    for i in 0..count {
+        digestion[i] = rand::thread_rng().gen_range(0.0, 1.0);
+    }
+    normalize(digestion, count)
+}
+
+/// Returns a slightly changed copy of the digestion efficiencies, which still add up to 1.0
+pub fn mutate_digestion(digestion: &Amounts, count: usize) -> Amounts
+{
+    let mut newdigestion = *digestion;
+    for i in 0..count {
+        newdigestion[i] = (digestion[i] + rand::thread_rng().gen_range(-0.05, 0.05)).max(0.0);
+    }
+    normalize(newdigestion, count)
+}
+
+/// Scales the efficiencies to add up to 1.0, so that a creature can either specialize in one resource, or digest several
+/// of them less efficiently
+fn normalize(mut digestion: Amounts, count: usize) -> Amounts
+{
+    let total : f64 = digestion.iter().take(count).sum();
+    for i in 0..MAX_RESOURCES {
+        digestion[i] = if i >= count { 0.0 } else if total > 0.0 { digestion[i] / total } else { 1.0 / count as f64 };
+    }
+    return digestion;
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resources_are_parsed()
+    {
+        let resource = Resource::from_str("fruit:0.5:50:1.5").unwrap();
+        assert_eq!(resource.name, "fruit");
+        assert_eq!((resource.rate, resource.max, resource.power, resource.inverse), (0.5, 50.0, 1.5, false));
+        assert!(Resource::from_str("moss:0:10:0:inverse").unwrap().inverse);
+    }
+
+    #[test]
+    fn invalid_resources_are_rejected()
+    {
+        for desc in &["food", "food:1:2", "food:1:2:3:4", "food:1:2:3:inverse:x", "food:one:2:3", "food:NaN:2:3", "food:1:inf:3"] {
+            assert!(Resource::from_str(desc).is_err(), "{} was accepted", desc);
+        }
+    }
+
+    #[test]
+    fn out_of_range_resources_are_rejected()
+    {
+        for desc in &["food:-0.1:100:2", "food:0.2:0:2", "food:0.2:-5:2", "food:0.2:100:-1"] {
+            assert!(Resource::from_str(desc).is_err(), "{} was accepted", desc);
+        }
+    }
+}
@@ -0,0 +1,108 @@
+
+use world::{ Terrain, TileKind, WorldPoint };
+
+
+pub const SCENT_CHANNELS : usize = 2;
+
+/// The most scent that can build up on one tile, in each channel
+pub const SCENT_MAX : f64 = 10.0;
+
+pub type Levels = [f64; SCENT_CHANNELS];
+
+#[derive(Copy, Clone, Debug)]
+pub struct ScentConfig {
+    /// The fraction of the scent on each tile that fades away every tick
+    pub decay: f64,
+    /// How much of the difference from the neighbouring tiles evens out every tick
+    pub diffusion: f64,
+}
+
+impl ScentConfig {
+    pub fn new() -> ScentConfig
+    {
+        ScentConfig {
+            decay: 0.01,
+            diffusion: 0.1,
+        }
+    }
+
+    /// Parses a description of the form decay:diffusion
+    pub fn from_str(desc: &str) -> Result<ScentConfig, String>
+    {
+        let parts : Vec<&str> = desc.split(':').collect();
+        if parts.len() != 2 {
+            return Err(format!("invalid scent {}, expected decay:diffusion", desc));
+        }
+
+        let number = |s: &str| s.parse::<f64>().map_err(|_| format!("invalid number {} in scent {}", s, desc));
+        Ok(ScentConfig {
+            decay: number(parts[0])?.max(0.0).min(1.0),
+            diffusion: number(parts[1])?.max(0.0).min(1.0),
+        })
+    }
+}
+
+
+/// A few channels of scent that creatures can leave on the tiles, which spread out to the neighbouring tiles and fade away
+/// over time
+pub struct ScentField {
+    pub config: ScentConfig,
+    levels: Vec<Vec<Levels>>,
+    /// The levels from the previous tick, kept to avoid allocating a new copy every tick
+    previous: Vec<Vec<Levels>>,
+}
+
+impl ScentField {
+    pub fn new(config: ScentConfig, size: [usize; 2]) -> ScentField
+    {
+        ScentField {
+            config: config,
+            levels: vec![vec![[0.0; SCENT_CHANNELS]; size[1]]; size[0]],
+            previous: vec![vec![[0.0; SCENT_CHANNELS]; size[1]]; size[0]],
+        }
+    }
+
+    pub fn level(&self, position: WorldPoint) -> Levels
+    {
+        self.levels[position[0] as usize][position[1] as usize]
+    }
+
+    pub fn deposit(&mut self, position: WorldPoint, channel: usize, amount: f64)
+    {
+        let level = &mut self.levels[position[0] as usize][position[1] as usize][channel];
+        *level = (*level + amount).min(SCENT_MAX);
+    }
+
+    /// Spreads the scent between each tile and its neighbours, and then fades it.  Walls don't hold any scent, and scent
+    /// doesn't leak out past an edge that doesn't wrap
+    pub fn timeslice(&mut self, terrain: &Terrain)
+    {
+        ::std::mem::swap(&mut self.levels, &mut self.previous);
+        let before = &self.previous;
+
+        for col in 0..terrain.size[0] {
+            for row in 0..terrain.size[1] {
+                if terrain.tiles[col][row].kind == TileKind::Wall {
+                    self.levels[col][row] = [0.0; SCENT_CHANNELS];
+                    continue;
+                }
+
+                let own = before[col][row];
+                let mut flow = [0.0; SCENT_CHANNELS];
+                for &(x, y) in terrain.neighbours(col, row).iter().filter_map(|neighbour| neighbour.as_ref()) {
+                    if terrain.tiles[x][y].kind != TileKind::Wall {
+                        for channel in 0..SCENT_CHANNELS {
+                            flow[channel] += before[x][y][channel] - own[channel];
+                        }
+                    }
+                }
+
+                for channel in 0..SCENT_CHANNELS {
+                    let level = own[channel] + self.config.diffusion * flow[channel] / 4.0;
+                    self.levels[col][row][channel] = (level * (1.0 - self.config.decay)).max(0.0);
+                }
+            }
+        }
+    }
+}
+
@@ -0,0 +1,146 @@
+
+use std::fmt;
+use std::io;
+use std::path::{ Path, PathBuf };
+
+use world::*;
+use library;
+use format;
+use halloffame;
+
+
+pub const DEFAULT_POPULATION : usize = 100;
+
+pub enum SeedSource {
+    File(String),
+    Directory(String),
+    Random(usize),
+    /// Mutated copies of each brain in a file or directory
+    Mutated(String, usize),
+}
+
+/// A source of creatures for the starting population.  If the colour isn't given, the sources are spread evenly over the
+/// colour range, and if the ancestor isn't given, each loaded or generated brain is the ancestor of its own lineage
+pub struct Seed {
+    pub source: SeedSource,
+    pub colour: Option<f32>,
+    pub ancestor: Option<CreatureID>,
+}
+
+impl Seed {
+    pub fn new(source: SeedSource) -> Seed
+    {
+        Seed {
+            source: source,
+            colour: None,
+            ancestor: None,
+        }
+    }
+}
+
+pub enum SeedError {
+    Load(PathBuf, io::Error),
+    Empty(String),
+}
+
+impl fmt::Display for SeedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match *self {
+            SeedError::Load(ref path, ref err) => write!(f, "failed to load creature from {}: {}", path.display(), err),
+            SeedError::Empty(ref source) => write!(f, "no creatures found in {}", source),
+        }
+    }
+}
+
+
+/// Creates the starting population from all the given sources.  All load failures are returned, rather than starting
+/// with fewer creatures than were asked for
+pub fn populate(seeds: &Vec<Seed>, terrain: &Terrain, birthday: WorldTime) -> Result<Vec<Creature>, Vec<SeedError>>
+{
+    let mut creatures = vec!();
+    let mut errors = vec!();
+
+    for (i, seed) in seeds.iter().enumerate() {
+        let colour = seed.colour.unwrap_or(i as f32 / seeds.len() as f32);
+        let ancestor = seed.ancestor.unwrap_or(-1);
+
+        match seed.source {
+            SeedSource::File(ref path) | SeedSource::Directory(ref path) => {
+                match load_brains(path, &mut errors) {
+                    Some(brains) => for brain in brains {
+                        creatures.push(Creature::generate(terrain, birthday, colour, ancestor, Some(brain)));
+                    },
+                    None => { },
+                }
+            },
+            SeedSource::Random(count) => {
+                for _ in 0..count {
+                    creatures.push(Creature::generate(terrain, birthday, colour, ancestor, None));
+                }
+            },
+            SeedSource::Mutated(ref path, count) => {
+                match load_brains(path, &mut errors) {
+                    Some(brains) => for brain in brains {
+                        let mut lineage = ancestor;
+                        for _ in 0..count {
+                            let creature = Creature::generate(terrain, birthday, colour, lineage, Some(brain.spawn()));
+                            lineage = creature.ancestor;
+                            creatures.push(creature);
+                        }
+                    },
+                    None => { },
+                }
+            },
+        }
+    }
+
+    if errors.len() > 0 {
+        return Err(errors);
+    }
+    return Ok(creatures);
+}
+
+/// Loads the brain in a file, or all the brains in a directory and its subdirectories, including the brains in archives
+pub fn load_brains(source: &str, errors: &mut Vec<SeedError>) -> Option<Vec<Brain>>
+{
+    let path = Path::new(source);
+    if halloffame::is_hall(path) {
+        return match halloffame::load_brains(path) {
+            Ok(brains) => Some(brains),
+            Err(err) => { errors.push(SeedError::Load(path.to_path_buf(), err)); None },
+        };
+    }
+
+    let (files, archives) = if path.is_dir() {
+        match (library::find_creature_files(path), library::find_archives(path)) {
+            (Ok(files), Ok(archives)) => (files, archives),
+            (Err(err), _) | (_, Err(err)) => { errors.push(SeedError::Load(path.to_path_buf(), err)); return None; },
+        }
+    } else if format::is_archive(path) {
+        (vec!(), vec!(path.to_path_buf()))
+    } else {
+        (vec!(path.to_path_buf()), vec!())
+    };
+
+    let mut brains = vec!();
+    for file in files {
+        match library::read_saved(&file) {
+            Ok(saved) => brains.push(saved.brain),
+            Err(err) => errors.push(SeedError::Load(file, err)),
+        }
+    }
+    for archive in archives {
+        match library::read_archive(&archive) {
+            Ok(creatures) => brains.extend(creatures.into_iter().map(|creature| creature.brain)),
+            Err(err) => errors.push(SeedError::Load(archive, err)),
+        }
+    }
+
+    if brains.len() <= 0 && errors.len() <= 0 {
+        errors.push(SeedError::Empty(source.to_string()));
+        return None;
+    }
+    return Some(brains);
+}
+
@@ -0,0 +1,64 @@
+
+use world::{ Creature, Terrain, Boundary };
+
+
+pub const SIGNAL_CHANNELS : usize = 2;
+
+/// How far away, in tiles, a signal can be heard
+pub const SIGNAL_RANGE : f64 = 5.0;
+
+pub type Signal = [f64; SIGNAL_CHANNELS];
+
+/// Returns what each creature hears from the signals of all the other creatures in range, where each signal is weighted by
+/// how close the creature that made it is, from 1.0 right next to it down to 0.0 at the edge of the range
+pub fn hear(creatures: &Vec<Creature>, terrain: &Terrain) -> Vec<Signal>
+{
+    // put the creatures into a grid of cells as big as the range, so only the neighbouring cells need to be searched
+    let cells = [ (terrain.size[0] as f64 / SIGNAL_RANGE).ceil() as usize, (terrain.size[1] as f64 / SIGNAL_RANGE).ceil() as usize ];
+    let cell_of = |creature: &Creature| [ (creature.position[0] / SIGNAL_RANGE) as usize, (creature.position[1] / SIGNAL_RANGE) as usize ];
+
+    let mut grid : Vec<Vec<Vec<usize>>> = vec![vec![vec!(); cells[1]]; cells[0]];
+    for (i, creature) in creatures.iter().enumerate() {
+        let cell = cell_of(creature);
+        grid[cell[0].min(cells[0] - 1)][cell[1].min(cells[1] - 1)].push(i);
+    }
+
+    let wraps = terrain.boundary == Boundary::Torus;
+    let mut heard = vec![[0.0; SIGNAL_CHANNELS]; creatures.len()];
+    for (i, listener) in creatures.iter().enumerate() {
+        let cell = cell_of(listener);
+
+        let mut searched = vec!();
+        for dx in 0..3 {
+            for dy in 0..3 {
+                let x = cell[0] as isize + dx - 1;
+                let y = cell[1] as isize + dy - 1;
+                let neighbour = if wraps {
+                    ((x + cells[0] as isize) as usize % cells[0], (y + cells[1] as isize) as usize % cells[1])
+                } else if x < 0 || y < 0 || x >= cells[0] as isize || y >= cells[1] as isize {
+                    continue;
+                } else {
+                    (x as usize, y as usize)
+                };
+
+                // on a small terrain, wrapping around can reach the same cell twice
+                if searched.contains(&neighbour) {
+                    continue;
+                }
+                searched.push(neighbour);
+
+                for &j in &grid[neighbour.0][neighbour.1] {
+                    let distance = terrain.distance(listener.position, creatures[j].position);
+                    if j != i && distance < SIGNAL_RANGE {
+                        let weight = 1.0 - distance / SIGNAL_RANGE;
+                        for channel in 0..SIGNAL_CHANNELS {
+                            heard[i][channel] += creatures[j].signal[channel] * weight;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    return heard;
+}
+
@@ -0,0 +1,277 @@
+
+use std::cmp::Ordering;
+use std::fs::{ self, File };
+use std::io::{ self, Write };
+use std::path::Path;
+
+use serde_json;
+
+use world::*;
+use diversity::Diversity;
+
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum StatsFormat {
+    Csv,
+    JsonLines,
+}
+
+impl StatsFormat {
+    pub fn from_filename(filename: &str) -> StatsFormat
+    {
+        if filename.ends_with(".json") || filename.ends_with(".jsonl") {
+            StatsFormat::JsonLines
+        } else {
+            StatsFormat::Csv
+        }
+    }
+}
+
+
+#[derive(Serialize)]
+pub struct Summary {
+    pub mean: f64,
+    pub median: f64,
+    pub max: f64,
+}
+
+impl Summary {
+    /// Summarises the values, leaving out any that aren't numbers
+    pub fn from(mut values: Vec<f64>) -> Summary
+    {
+        values.retain(|value| !value.is_nan());
+        if values.len() <= 0 {
+            return Summary { mean: 0.0, median: 0.0, max: 0.0 };
+        }
+
+        values.sort_by(|a, b| a.total_cmp(b));
+
+        let mid = values.len() / 2;
+        let median = if values.len() % 2 == 0 { (values[mid - 1] + values[mid]) / 2.0 } else { values[mid] };
+
+        Summary {
+            mean: values.iter().sum::<f64>() / values.len() as f64,
+            median: median,
+            max: values[values.len() - 1],
+        }
+    }
+}
+
+
+/// Orders scores from highest to lowest, with any that aren't numbers last, so that a bad score can't take the top place
+pub fn highest_first(a: f64, b: f64) -> Ordering
+{
+    match (a.is_nan(), b.is_nan()) {
+        (false, false) => b.total_cmp(&a),
+        (a_nan, b_nan) => a_nan.cmp(&b_nan),
+    }
+}
+
+
+#[derive(Serialize)]
+pub struct Sample {
+    pub time: WorldTime,
+    pub population: usize,
+    pub births: usize,
+    pub deaths: usize,
+    pub arrivals: usize,
+    pub food: f64,
+    pub season: f64,
+    pub climate: String,
+    pub size: Summary,
+    pub age: Summary,
+    pub eaten_rate: Summary,
+    pub spawns: Summary,
+    pub mean_distance: f64,
+    pub species: usize,
+    pub ancestor_distance: f64,
+    pub layer_variance: Vec<f64>,
+}
+
+impl Sample {
+    pub fn from(world: &World, diversity: &Diversity, births: usize, deaths: usize, arrivals: usize) -> Sample
+    {
+        let age = |creature: &Creature| (world.time - creature.birthday).max(1) as f64;
+
+        Sample {
+            time: world.time,
+            population: world.creatures.len(),
+            births: births,
+            deaths: deaths,
+            arrivals: arrivals,
+            food: world.terrain.total_food(),
+            season: world.terrain.climate.season(),
+            climate: world.terrain.climate.describe(),
+            size: Summary::from(world.creatures.iter().map(|c| c.size).collect()),
+            age: Summary::from(world.creatures.iter().map(|c| age(c)).collect()),
+            eaten_rate: Summary::from(world.creatures.iter().map(|c| c.eaten / age(c)).collect()),
+            spawns: Summary::from(world.creatures.iter().map(|c| c.spawns as f64).collect()),
+            mean_distance: diversity.mean_distance,
+            species: diversity.species,
+            ancestor_distance: diversity.mean_ancestor_distance(),
+            layer_variance: diversity.layer_variance.clone(),
+        }
+    }
+
+    fn csv_header() -> String
+    {
+        let mut columns = vec!("time", "population", "births", "deaths", "arrivals", "food", "season", "climate").iter().map(|s| s.to_string()).collect::<Vec<String>>();
+        for name in &["size", "age", "eaten_rate", "spawns"] {
+            for stat in &["mean", "median", "max"] {
+                columns.push(format!("{}_{}", name, stat));
+            }
+        }
+        for name in &["mean_distance", "species", "ancestor_distance", "layer_variance"] {
+            columns.push(name.to_string());
+        }
+        return columns.join(",");
+    }
+
+    fn to_csv(&self) -> String
+    {
+        let mut columns = vec!(self.time.to_string(), self.population.to_string(), self.births.to_string(), self.deaths.to_string(), self.arrivals.to_string(), self.food.to_string(), self.season.to_string(), csv_quote(&self.climate));
+        for summary in &[&self.size, &self.age, &self.eaten_rate, &self.spawns] {
+            columns.push(summary.mean.to_string());
+            columns.push(summary.median.to_string());
+            columns.push(summary.max.to_string());
+        }
+        columns.push(self.mean_distance.to_string());
+        columns.push(self.species.to_string());
+        columns.push(self.ancestor_distance.to_string());
+        columns.push(self.layer_variance.iter().map(|v| v.to_string()).collect::<Vec<String>>().join(";"));
+        return columns.join(",");
+    }
+}
+
+/// Quotes a text field for csv if it has any commas, quotes or line breaks, which can come from a climate schedule's labels
+fn csv_quote(text: &str) -> String
+{
+    if text.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        return format!("\"{}\"", text.replace('"', "\"\""));
+    }
+    return text.to_string();
+}
+
+
+/// Samples the world every `interval` ticks and appends a row to the output file
+pub struct StatsRecorder {
+    format: StatsFormat,
+    interval: WorldTime,
+    output: File,
+
+    last_time: WorldTime,
+    last_births: usize,
+    last_deaths: usize,
+    last_arrivals: usize,
+}
+
+impl StatsRecorder {
+    pub fn new(filename: &str, format: StatsFormat, interval: WorldTime) -> Result<StatsRecorder, io::Error>
+    {
+        if let Some(dir) = Path::new(filename).parent() {
+            if dir != Path::new("") {
+                fs::create_dir_all(dir)?;
+            }
+        }
+
+        let mut output = File::create(filename)?;
+        if format == StatsFormat::Csv {
+            writeln!(output, "{}", Sample::csv_header())?;
+        }
+
+        Ok(StatsRecorder {
+            format: format,
+            interval: interval,
+            output: output,
+
+            last_time: 0,
+            last_births: 0,
+            last_deaths: 0,
+            last_arrivals: 0,
+        })
+    }
+
+    /// Returns true if a row is due to be written at this time
+    pub fn due(&self, time: WorldTime) -> bool
+    {
+        time % self.interval == 0 && time != self.last_time
+    }
+
+    /// Writes a row if one is due, using the diversity measured at this time
+    pub fn update(&mut self, world: &World, diversity: &Diversity) -> Result<(), io::Error>
+    {
+        if !self.due(world.time) {
+            return Ok(());
+        }
+        self.last_time = world.time;
+
+        let sample = Sample::from(world, diversity, world.births - self.last_births, world.deaths - self.last_deaths, world.arrivals - self.last_arrivals);
+        self.last_births = world.births;
+        self.last_deaths = world.deaths;
+        self.last_arrivals = world.arrivals;
+
+        self.write(&sample)
+    }
+
+    fn write(&mut self, sample: &Sample) -> Result<(), io::Error>
+    {
+        match self.format {
+            StatsFormat::Csv => writeln!(self.output, "{}", sample.to_csv()),
+            StatsFormat::JsonLines => {
+                match serde_json::to_string(sample) {
+                    Ok(encoded) => writeln!(self.output, "{}", encoded),
+                    Err(err) => Err(io::Error::new(io::ErrorKind::InvalidData, format!("error encoding json: {}", err))),
+                }
+            },
+        }
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use std::f64;
+    use super::*;
+
+    #[test]
+    fn summary_of_nothing_is_zero()
+    {
+        let summary = Summary::from(vec!());
+        assert_eq!((summary.mean, summary.median, summary.max), (0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn summary_median_of_even_count()
+    {
+        let summary = Summary::from(vec!(4.0, 1.0, 3.0, 2.0));
+        assert_eq!((summary.mean, summary.median, summary.max), (2.5, 2.5, 4.0));
+    }
+
+    #[test]
+    fn summary_leaves_out_nan()
+    {
+        let summary = Summary::from(vec!(3.0, f64::NAN, 1.0));
+        assert_eq!((summary.mean, summary.median, summary.max), (2.0, 2.0, 3.0));
+
+        let summary = Summary::from(vec!(f64::NAN));
+        assert_eq!((summary.mean, summary.median, summary.max), (0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn highest_first_puts_nan_last()
+    {
+        let mut scores = vec!(1.0, f64::NAN, 3.0, f64::NEG_INFINITY, 2.0);
+        scores.sort_by(|a, b| highest_first(*a, *b));
+        assert_eq!(&scores[..4], &[3.0, 2.0, 1.0, f64::NEG_INFINITY]);
+        assert!(scores[4].is_nan());
+    }
+
+    #[test]
+    fn csv_fields_are_quoted_when_needed()
+    {
+        assert_eq!(csv_quote("ice age"), "ice age");
+        assert_eq!(csv_quote("warm, wet"), "\"warm, wet\"");
+        assert_eq!(csv_quote("the \"big\" freeze"), "\"the \"\"big\"\" freeze\"");
+    }
+}
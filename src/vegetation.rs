@@ -0,0 +1,135 @@
+
+use world::{ Terrain, TileKind };
+use generator::MAX_TTYPE;
+
+
+/// An alternative way of growing food, where the food on each tile grows logistically at a rate set by the tile's
+/// fertility, up to a limit that's lower than the resource's max, spreads into neighbouring tiles, and dies off if it's
+/// grazed too low, after which the tile lies fallow for a while before it recovers on its own
+#[derive(Copy, Clone, Debug)]
+pub struct Vegetation {
+    /// The fraction of the remaining room that fills up on each growth step, on the most fertile tiles
+    pub rate: f64,
+    /// The limit on each tile, as a fraction of each resource's max
+    pub capacity: f64,
+    /// The fraction of the neighbouring tiles' food that seeds a fallow tile on each growth step
+    pub spread: f64,
+    /// The fraction of the limit below which the food dies off
+    pub threshold: f64,
+    /// The number of growth steps a tile lies fallow after dying off, unless it's seeded by its neighbours first
+    pub recovery: u16,
+}
+
+impl Vegetation {
+    pub fn new() -> Vegetation
+    {
+        Vegetation {
+            rate: 0.2,
+            capacity: 0.5,
+            spread: 0.05,
+            threshold: 0.1,
+            recovery: 50,
+        }
+    }
+
+    /// Parses a description of the form rate:capacity:spread:threshold:recovery
+    pub fn from_str(desc: &str) -> Result<Vegetation, String>
+    {
+        let parts : Vec<&str> = desc.split(':').collect();
+        if parts.len() != 5 {
+            return Err(format!("invalid vegetation {}, expected rate:capacity:spread:threshold:recovery", desc));
+        }
+
+        let number = |s: &str| match s.parse::<f64>() {
+            Ok(value) if value.is_finite() => Ok(value),
+            _ => Err(format!("invalid number {} in vegetation {}", s, desc)),
+        };
+        let vegetation = Vegetation {
+            rate: number(parts[0])?,
+            capacity: number(parts[1])?,
+            spread: number(parts[2])?,
+            threshold: number(parts[3])?,
+            recovery: parts[4].parse().map_err(|_| format!("invalid number {} in vegetation {}", parts[4], desc))?,
+        };
+
+        if vegetation.rate < 0.0 || vegetation.spread < 0.0 {
+            return Err(format!("invalid vegetation {}, the rate and spread can't be negative", desc));
+        }
+        if vegetation.capacity <= 0.0 || vegetation.capacity > 1.0 {
+            return Err(format!("invalid vegetation {}, the capacity has to be more than 0 and at most 1", desc));
+        }
+        // fallow tiles regrow to twice the threshold, which has to stay within the limit
+        if vegetation.threshold < 0.0 || vegetation.threshold > 0.5 {
+            return Err(format!("invalid vegetation {}, the threshold has to be between 0 and 0.5", desc));
+        }
+        Ok(vegetation)
+    }
+
+    pub fn grow(&self, terrain: &mut Terrain)
+    {
+        // spreading uses the food from before this step, so the order the tiles are updated in doesn't matter
+        let before : Vec<Vec<_>> = terrain.tiles.iter().map(|column| column.iter().map(|tile| (tile.food, tile.fallow)).collect()).collect();
+        let (width, height) = (terrain.size[0], terrain.size[1]);
+
+        for col in 0..width {
+            for row in 0..height {
+                if terrain.tiles[col][row].kind == TileKind::Wall {
+                    continue;
+                }
+
+                let growth = terrain.climate.growth(col, row);
+                let neighbours = terrain.neighbours(col, row);
+
+                let tile = &mut terrain.tiles[col][row];
+                for (i, resource) in terrain.resources.iter().enumerate() {
+                    let fertility = resource.fertility(tile.ttype, MAX_TTYPE);
+                    let limit = if fertility > 0.0 { self.capacity * resource.max } else { 0.0 };
+
+                    if tile.fallow[i] > 0 {
+                        let seeds : f64 = neighbours.iter().filter_map(|&neighbour| neighbour).filter(|&(x, y)| before[x][y].1[i] == 0).map(|(x, y)| before[x][y].0[i]).sum();
+                        // tiles on an edge that doesn't wrap have fewer neighbours, but are seeded as if the missing ones were empty
+                        tile.food[i] += self.spread * growth * fertility * seeds / 4.0;
+                        tile.fallow[i] -= 1;
+                        if tile.food[i] >= self.threshold * limit * 2.0 || tile.fallow[i] == 0 {
+                            tile.food[i] = tile.food[i].max(self.threshold * limit * 2.0);
+                            tile.fallow[i] = 0;
+                        }
+                    } else if limit > 0.0 && tile.food[i] < self.threshold * limit {
+                        tile.food[i] = 0.0;
+                        tile.fallow[i] = self.recovery.max(1);
+                    } else if limit > 0.0 {
+                        tile.food[i] += self.rate * growth * fertility * tile.food[i] * (1.0 - tile.food[i] / limit);
+                    }
+
+                    tile.food[i] = tile.food[i].min(limit).max(0.0);
+                }
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vegetation_is_parsed()
+    {
+        let vegetation = Vegetation::from_str("0.3:1:0:0.5:20").unwrap();
+        assert_eq!((vegetation.rate, vegetation.capacity, vegetation.spread, vegetation.threshold, vegetation.recovery), (0.3, 1.0, 0.0, 0.5, 20));
+    }
+
+    #[test]
+    fn invalid_vegetation_is_rejected()
+    {
+        let invalid = [
+            "0.2:0.5:0.05:0.1", "0.2:0.5:0.05:0.1:50:1", "0.2:half:0.05:0.1:50", "0.2:0.5:0.05:0.1:-1", "nan:0.5:0.05:0.1:50",
+            "-0.2:0.5:0.05:0.1:50", "0.2:0.5:-0.05:0.1:50", "0.2:0:0.05:0.1:50", "0.2:1.5:0.05:0.1:50", "0.2:0.5:0.05:-0.1:50",
+            "0.2:0.5:0.05:0.6:50",
+        ];
+        for desc in &invalid {
+            assert!(Vegetation::from_str(desc).is_err(), "{} was accepted", desc);
+        }
+    }
+}
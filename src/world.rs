@@ -2,57 +2,149 @@
 use std::cmp;
 use std::f32;
 use std::f64;
-use std::fs::File;
-use std::io::{ self, Write, Read, ErrorKind };
+use std::collections::{ HashMap, HashSet };
 
 extern crate rand;
 use self::rand::Rng;
 
-extern crate rustc_serialize;
-//use self::rustc_serialize::*;
+use seed::{ self, Seed, SeedError };
+use generator::{ self, TerrainConfig, MAX_TTYPE };
+use resource::{ self, Resource, Amounts, MAX_RESOURCES };
+use metabolism::Metabolism;
+use aging::{ self, Aging };
+use action::{ Action, Actions, ACTIONS };
+use population::Population;
+use novelty::{ Archive, Behaviour, NOVELTY_INTERVAL };
+use plasticity::{ self, Plasticity };
+use climate::Climate;
+use vegetation::Vegetation;
+use scent::{ self, ScentField, SCENT_CHANNELS };
+use signal::{ self, Signal, SIGNAL_CHANNELS };
 
 
 pub type WorldTime = u64;
 
+/// How creatures decide when to reproduce
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Reproduction {
+    /// Creatures that are large enough have a small random chance of budding, and always give half of themselves
+    Random,
+    /// The brain decides when to reproduce, and each creature gives an inherited fraction of itself to its child
+    Brain,
+}
+
+impl Reproduction {
+    pub fn from_str(name: &str) -> Option<Reproduction>
+    {
+        match name {
+            "random" => Some(Reproduction::Random),
+            "brain" => Some(Reproduction::Brain),
+            _ => None,
+        }
+    }
+}
+
+/// What a creature is told to do for a tick by something outside the world, such as a learning agent, instead of by its
+/// brain
+#[derive(Clone, Debug)]
+pub enum Control {
+    /// Values for each of the brain's outputs, which are carried out as if the brain had produced them
+    Outputs(Vec<f64>),
+    /// One of the discrete actions, when the creatures choose one action at a time
+    Action(Action),
+}
+
+/// Options that change how the creatures live
+#[derive(Clone, Debug)]
+pub struct Rules {
+    /// Creatures turn the resources they eat into energy using evolved reactions, instead of growing from food directly
+    pub metabolism: bool,
+    /// Creatures can make signals that other creatures nearby can hear
+    pub signals: bool,
+    /// Creatures grow old, and become more costly to keep alive, less fertile, and more likely to die with age
+    pub aging: Option<Aging>,
+    pub reproduction: Reproduction,
+    /// Creatures choose one action on each tick, such as eating or moving, instead of always moving and eating
+    pub actions: Option<Actions>,
+    /// Creatures with more novel behaviour are more likely to reproduce, by up to this multiple of their novelty
+    pub novelty: Option<f64>,
+    /// Brains learn during the creature's life, at inherited rates
+    pub plasticity: Option<Plasticity>,
+}
+
+impl Rules {
+    pub fn new() -> Rules
+    {
+        Rules {
+            metabolism: false,
+            signals: false,
+            aging: None,
+            reproduction: Reproduction::Random,
+            actions: None,
+            novelty: None,
+            plasticity: None,
+        }
+    }
+}
+
 pub struct World {
     pub run: bool,
     pub time: WorldTime,
+    pub rules: Rules,
     pub terrain: Terrain,
     pub creatures: Vec<Creature>,
     pub total_lives: usize,
+    pub births: usize,
+    pub deaths: usize,
+    /// The number of creatures added to keep up the population, or as immigrants
+    pub arrivals: usize,
+    pub founders: HashMap<CreatureID, Brain>,
+    pub lineage: Lineage,
+    pub population: Option<Population>,
+    /// The behaviours that have been novel enough to remember, when novelty is a bonus to reproduction
+    pub archive: Option<Archive>,
 }
 
 impl World {
-    pub fn new() -> World
+    pub fn new(seeds: &Vec<Seed>, terrain: Terrain, rules: Rules) -> Result<World, Vec<SeedError>>
     {
-        let terrain = Terrain::new();
-        let preloads : Vec<i32> = vec!(1237, 1368, 1968, 2128, 138438, 150268);
-
-        let mut creatures : Vec<Creature> = Vec::new();
-        for i in 0..CREAT_INIT {
-            let colour = i as f32 / CREAT_INIT as f32;
-
-            //let creature = Creature::generate(terrain.size, 1, colour, -1, None);
-            //*
-            let creature = match Creature::read(&format!("creatures/{}.json", preloads[i as usize % preloads.len()]), terrain.size, 1, i % preloads.len() as CreatureID + 1) {
-                Ok(creature) => creature,
-                Err(err) => { println!("{}", err); continue; },
-            };
-            //*/
+        match seed::populate(seeds, &terrain, 1) {
+            Ok(creatures) => Ok(World::from_creatures(creatures, terrain, rules)),
+            Err(errors) => Err(errors),
+        }
+    }
 
-            creatures.push(creature);
+    pub fn from_creatures(creatures: Vec<Creature>, terrain: Terrain, rules: Rules) -> World
+    {
+        let mut founders = HashMap::new();
+        for creature in &creatures {
+            founders.entry(creature.ancestor).or_insert_with(|| creature.brain.clone());
         }
 
         World {
             run: true,
             time: 1,
+            rules: rules,
             terrain: terrain,
             total_lives: creatures.len(),
             creatures: creatures,
+            births: 0,
+            deaths: 0,
+            arrivals: 0,
+            founders: founders,
+            lineage: Lineage::new(),
+            population: None,
+            archive: None,
         }
     }
 
     pub fn timeslice(&mut self)
+    {
+        self.timeslice_with(&HashMap::new());
+    }
+
+    /// Runs one tick, where the creatures with controls do as they're told, and the rest are driven by their brains
+    pub fn timeslice_with(&mut self, controls: &HashMap<CreatureID, Control>)
     {
         if !self.run {
             return;
@@ -64,31 +156,182 @@ impl World {
 
         let mut newcreats : Vec<Creature> = vec!();
 
-        for creature in &mut self.creatures {
-            creature.timeslice(&mut self.terrain);
+        if self.rules.novelty.is_some() && self.time % NOVELTY_INTERVAL == 0 {
+            let descriptors = self.creatures.iter().map(|creature| creature.behaviour.descriptor()).collect();
+            let novelty = self.archive.get_or_insert_with(Archive::new).measure(&descriptors);
+            for (creature, novelty) in self.creatures.iter_mut().zip(novelty) {
+                creature.novelty = novelty;
+            }
+        }
 
-            //if self.time - creature.lastbirth > 1000 && creature.size > 0.75 {
-            if self.time - creature.lastbirth > 100 && creature.size > 0.75 && rand::thread_rng().gen_range(0.0, 1.0) <= 0.001 {
+        let heard = if self.rules.signals {
+            signal::hear(&self.creatures, &self.terrain)
+        } else {
+            vec![[0.0; SIGNAL_CHANNELS]; self.creatures.len()]
+        };
+
+        let mut attackers = vec!();
+        for (i, (creature, heard)) in self.creatures.iter_mut().zip(heard).enumerate() {
+            let action = match controls.get(&creature.id) {
+                Some(control) => creature.control(&mut self.terrain, &self.rules, control, self.time),
+                None => creature.timeslice(&mut self.terrain, &self.rules, heard, self.time),
+            };
+            if action == Some(Action::Attack) {
+                attackers.push(i);
+            }
+
+            let age = self.time - creature.birthday;
+            let fertility = self.rules.aging.map_or(1.0, |aging| aging.fertility(age, creature.longevity)) * (1.0 + self.rules.novelty.unwrap_or(0.0) * creature.novelty);
+            let (spawning, investment) = match self.rules.reproduction {
+                //Reproduction::Random => (self.time - creature.lastbirth > 1000 && creature.size > 0.75, 0.5),
+                Reproduction::Random => (self.time - creature.lastbirth > 100 && creature.size > 0.75 && rand::thread_rng().gen_range(0.0, 1.0) <= 0.001 * fertility, 0.5),
+                Reproduction::Brain => {
+                    // both the parent and the child have to be left large enough to survive
+                    let viable = creature.size * creature.investment.min(1.0 - creature.investment) >= MIN_SIZE;
+                    (action == Some(Action::Reproduce) && viable && self.time - creature.lastbirth > 100 && rand::thread_rng().gen_range(0.0, 1.0) < fertility, creature.investment)
+                },
+            };
+
+            if spawning {
                 creature.lastbirth = self.time;
-                let mut newcreature = creature.spawn(self.time);
+                let mut newcreature = creature.spawn(self.time, self.terrain.resources.len(), investment);
                 self.total_lives += 1;
-                newcreature.position = self.terrain.wrap_position(newcreature.position);
+                self.births += 1;
+                self.lineage.record_birth(newcreature.id, creature.id);
+                newcreature.position = match self.terrain.wrap_position(newcreature.position) {
+                    Some(position) if !self.terrain.is_blocked(position) => position,
+                    _ => creature.position,
+                };
                 newcreats.push(newcreature);
             }
         }
 
+        for attacker in attackers {
+            self.attack(attacker);
+        }
+
         for newcreat in newcreats {
             self.creatures.push(newcreat);
         }
 
-        self.creatures.retain(|ref creature| creature.size >= 0.25);
-        //let world_time = self.time;
-        //self.creatures.retain(|ref creature| creature.size >= 0.25 && world_time - creature.birthday < 10000);
+        let population = self.creatures.len();
+        self.creatures.retain(|ref creature| creature.size >= MIN_SIZE);
+        self.deaths += population - self.creatures.len();
+
+        if let Some(population) = self.population.take() {
+            population.timeslice(self);
+            self.population = Some(population);
+        }
+
+        if self.time % LINEAGE_INTERVAL == 0 {
+            self.lineage.prune(&self.creatures);
+            self.prune_founders();
+        }
 
         if self.creatures.len() <= 0 {
             self.run = false;
         }
     }
+
+    /// Adds a creature that wasn't born in the world, which starts its own lineage
+    pub fn arrive(&mut self, creature: Creature)
+    {
+        self.founders.entry(creature.ancestor).or_insert_with(|| creature.brain.clone());
+        self.total_lives += 1;
+        self.arrivals += 1;
+        self.creatures.push(creature);
+    }
+
+    /// Forgets the brains of founders whose lineages have died out, since immigrants and reseeded creatures keep starting
+    /// new lineages on long runs
+    fn prune_founders(&mut self)
+    {
+        let ancestors : HashSet<CreatureID> = self.creatures.iter().map(|creature| creature.ancestor).collect();
+        self.founders.retain(|id, _| ancestors.contains(id));
+    }
+
+    /// Takes a bite out of the nearest creature in reach of the attacker, if there is one, and gives the attacker part of it
+    fn attack(&mut self, attacker: usize)
+    {
+        let position = self.creatures[attacker].position;
+        let mut nearest = None;
+        for (i, creature) in self.creatures.iter().enumerate() {
+            let distance = self.terrain.distance(position, creature.position);
+            if i != attacker && creature.size >= MIN_SIZE && distance < ATTACK_RANGE && nearest.map_or(true, |(_, best)| distance < best) {
+                nearest = Some((i, distance));
+            }
+        }
+
+        if let Some((victim, _)) = nearest {
+            let bite = self.creatures[victim].size.min(ATTACK_DAMAGE);
+            self.creatures[victim].size -= bite;
+            self.creatures[attacker].size += bite * ATTACK_GAIN;
+        }
+    }
+}
+
+
+/// How often the records of lineages that have died out are forgotten
+const LINEAGE_INTERVAL : WorldTime = 1000;
+
+/// Records the parent of every creature born in the world, and how many descendants each creature has had.  Only the
+/// living creatures and their ancestors are remembered, so that long runs don't keep a record of every creature that ever
+/// lived
+pub struct Lineage {
+    parents: HashMap<CreatureID, CreatureID>,
+    descendants: HashMap<CreatureID, u32>,
+}
+
+impl Lineage {
+    fn new() -> Lineage
+    {
+        Lineage {
+            parents: HashMap::new(),
+            descendants: HashMap::new(),
+        }
+    }
+
+    fn record_birth(&mut self, child: CreatureID, parent: CreatureID)
+    {
+        self.parents.insert(child, parent);
+
+        let mut ancestor = Some(parent);
+        while let Some(id) = ancestor {
+            *self.descendants.entry(id).or_insert(0) += 1;
+            ancestor = self.parents.get(&id).cloned();
+        }
+    }
+
+    /// Forgets the creatures that are dead and have no living descendants.  The descendants of dead creatures are never
+    /// asked for, so they're only counted for the living
+    fn prune(&mut self, living: &Vec<Creature>)
+    {
+        let mut kept = HashSet::new();
+        for creature in living {
+            let mut ancestor = Some(creature.id);
+            while let Some(id) = ancestor {
+                // the rest of the line has already been kept by another creature
+                if !kept.insert(id) {
+                    break;
+                }
+                ancestor = self.parents.get(&id).cloned();
+            }
+        }
+
+        let alive : HashSet<CreatureID> = living.iter().map(|creature| creature.id).collect();
+        self.parents.retain(|id, _| kept.contains(id));
+        self.descendants.retain(|id, _| alive.contains(id));
+    }
+
+    pub fn parent(&self, id: CreatureID) -> Option<CreatureID>
+    {
+        self.parents.get(&id).cloned()
+    }
+
+    pub fn descendants(&self, id: CreatureID) -> u32
+    {
+        *self.descendants.get(&id).unwrap_or(&0)
+    }
 }
 
 
@@ -97,60 +340,160 @@ const WORLD_HEIGHT: usize = 100;
 
 pub type WorldPoint = [f64; 2];
 
+/// What happens at the edges of the terrain
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Boundary {
+    /// The edges wrap around to the opposite side
+    Torus,
+    /// Creatures stop at the edges
+    Wall,
+    /// Creatures bounce off the edges
+    Bounce,
+    /// Creatures that move past the edges die
+    Lethal,
+}
+
+impl Boundary {
+    pub fn from_str(name: &str) -> Option<Boundary>
+    {
+        match name {
+            "torus" | "wrap" => Some(Boundary::Torus),
+            "wall" => Some(Boundary::Wall),
+            "bounce" => Some(Boundary::Bounce),
+            "lethal" => Some(Boundary::Lethal),
+            _ => None,
+        }
+    }
+
+    /// Returns the distance between two points in an area of the given size, which can be across the edges on a torus
+    pub fn distance(&self, a: WorldPoint, b: WorldPoint, size: [usize; 2]) -> f64
+    {
+        let mut dx = (a[0] - b[0]).abs();
+        let mut dy = (a[1] - b[1]).abs();
+        if *self == Boundary::Torus {
+            dx = dx.min(size[0] as f64 - dx);
+            dy = dy.min(size[1] as f64 - dy);
+        }
+        (dx * dx + dy * dy).sqrt()
+    }
+
+    /// The value seen by a creature's terrain sensors when looking past the edge
+    pub fn sense(&self) -> f64
+    {
+        match *self {
+            Boundary::Lethal => TileKind::Hazard.sense(),
+            _ => TileKind::Wall.sense(),
+        }
+    }
+}
+
 pub struct Terrain {
     pub size: [usize; 2],
     pub tiles: [[Tile; WORLD_HEIGHT]; WORLD_WIDTH],
-
-    pub season: f64,
-    pub season_height: usize,
+    pub resources: Vec<Resource>,
+    /// The amount of food below which a tile isn't counted as having plenty of food, which depends only on the resources
+    pub rich_food: f64,
+    pub boundary: Boundary,
+    pub climate: Box<dyn Climate>,
+    pub growth_interval: WorldTime,
+    pub vegetation: Option<Vegetation>,
+    pub scent: Option<ScentField>,
 }
 
 impl Terrain {
-    fn new() -> Terrain
+    pub fn new(config: &TerrainConfig) -> Terrain
     {
-        let mut tiles = [[Tile { ttype: 0, food: 0.0 }; WORLD_HEIGHT]; WORLD_WIDTH];
+        let ttypes = generator::generate(config, [ WORLD_WIDTH, WORLD_HEIGHT ]);
+
+        let mut terrain = Terrain::empty(config);
         for col in 0..WORLD_WIDTH {
             for row in 0..WORLD_HEIGHT {
-                //tiles[col][row] = Tile::new(rand::thread_rng().gen_range(0, 5));
-
-                let left = if col > 0 { tiles[col - 1][row].ttype } else { rand::thread_rng().gen_range(0, 5) };
-                let top = if row > 0 { tiles[col][row - 1].ttype } else { rand::thread_rng().gen_range(0, 5) };
-                //let middle = cmp::min((left + top) / 2 + rand::thread_rng().gen_range(0, 2), 5);
-                let middle = (left + top) / 2;
-                let random = rand::thread_rng().gen_range(0.0, 1.0);
-
-                let ttype = if random <= 0.25 {
-                    //cmp::max(middle - 1, 1)
-                    if middle > 0 { middle - 1 } else { 1 }
-                }
-                else if random >= 0.75 {
-                    //cmp::min(middle + 1, 5)
-                    if middle < 5 { middle + 1 } else { 5 }
-                }
-                else {
-                    middle
-                };
-
-                tiles[col][row] = Tile::new(ttype);
-                print!("{} ", ttype);
+                terrain.tiles[col][row] = Tile::new(ttypes[col][row], &config.resources);
             }
-            println!("");
         }
+        return terrain;
+    }
 
+    pub fn empty(config: &TerrainConfig) -> Terrain
+    {
         Terrain {
             size: [ WORLD_WIDTH, WORLD_HEIGHT ],
-            tiles: tiles,
-            season: 0.0,
-            season_height: 0,
+            tiles: [[Tile { ttype: 0, food: [0.0; MAX_RESOURCES], kind: TileKind::Ground, fallow: [0; MAX_RESOURCES] }; WORLD_HEIGHT]; WORLD_WIDTH],
+            resources: config.resources.clone(),
+            rich_food: config.resources.iter().map(|resource| resource.max).fold(0.0, f64::max) * RICH_FOOD,
+            boundary: config.boundary,
+            climate: config.climate.build([ WORLD_WIDTH, WORLD_HEIGHT ], config.boundary),
+            growth_interval: config.growth_interval.max(1),
+            vegetation: config.vegetation,
+            scent: config.scent.map(|scent| ScentField::new(scent, [ WORLD_WIDTH, WORLD_HEIGHT ])),
         }
     }
 
-    fn wrap_position(&self, position : WorldPoint) -> WorldPoint
+    pub fn tile_at(&self, position: WorldPoint) -> &Tile
+    {
+        &self.tiles[position[0] as usize][position[1] as usize]
+    }
+
+    pub fn is_blocked(&self, position: WorldPoint) -> bool
+    {
+        self.tile_at(position).kind == TileKind::Wall
+    }
+
+    /// Returns a random position that isn't blocked or hazardous, or any random position if none could be found
+    pub fn random_position(&self) -> WorldPoint
+    {
+        let mut position = [0.0, 0.0];
+        for _ in 0..1000 {
+            position = [ rand::thread_rng().gen_range(0.0, self.size[0] as f64), rand::thread_rng().gen_range(0.0, self.size[1] as f64) ];
+            if !self.is_blocked(position) && self.tile_at(position).kind != TileKind::Hazard {
+                break;
+            }
+        }
+        return position;
+    }
+
+    /// Returns the position inside the terrain that a point refers to, which wraps around on a torus, or None if the point
+    /// is past an edge that doesn't wrap
+    pub fn wrap_position(&self, position : WorldPoint) -> Option<WorldPoint>
     {
         let mut newpos : WorldPoint = [0.0, 0.0];
-        if position[0] < 0.0 { newpos[0] = self.size[0] as f64 - 0.1 } else if position[0] >= self.size[0] as f64 { newpos[0] = 0.0 } else { newpos[0] = position[0] }
-        if position[1] < 0.0 { newpos[1] = self.size[1] as f64 - 0.1 } else if position[1] >= self.size[1] as f64 { newpos[1] = 0.0 } else { newpos[1] = position[1] }
-        return newpos;
+        for i in 0..2 {
+            let size = self.size[i] as f64;
+            if self.boundary == Boundary::Torus {
+                newpos[i] = position[i].rem_euclid(size);
+                // rounding can leave a tiny negative number at the very end
+                if newpos[i] >= size {
+                    newpos[i] = 0.0;
+                }
+            } else if position[i] < 0.0 || position[i] >= size {
+                return None;
+            } else {
+                newpos[i] = position[i];
+            }
+        }
+        return Some(newpos);
+    }
+
+    /// Returns the distance between two points, which can be across the edges on a torus
+    pub fn distance(&self, a: WorldPoint, b: WorldPoint) -> f64
+    {
+        self.boundary.distance(a, b, self.size)
+    }
+
+    /// Returns the tiles to the left, right, above and below a tile, with None for any past an edge that doesn't wrap
+    pub fn neighbours(&self, col: usize, row: usize) -> [Option<(usize, usize)>; 4]
+    {
+        let (width, height) = (self.size[0], self.size[1]);
+        if self.boundary == Boundary::Torus {
+            return [ Some(((col + width - 1) % width, row)), Some(((col + 1) % width, row)), Some((col, (row + height - 1) % height)), Some((col, (row + 1) % height)) ];
+        }
+
+        [
+            if col > 0 { Some((col - 1, row)) } else { None },
+            if col + 1 < width { Some((col + 1, row)) } else { None },
+            if row > 0 { Some((col, row - 1)) } else { None },
+            if row + 1 < height { Some((col, row + 1)) } else { None },
+        ]
     }
 
     pub fn total_food(&self) -> f64
@@ -159,7 +502,7 @@ impl Terrain {
 
         for col in 0..self.size[0] {
             for row in 0..self.size[1] {
-                sum += self.tiles[col][row].food;
+                sum += self.tiles[col][row].total_food();
             }
         }
         return sum;
@@ -167,67 +510,157 @@ impl Terrain {
 
     fn timeslice(&mut self, time : WorldTime)
     {
-        const TIMES_PER_YEAR : f64 = 10000.0;
-        //self.season = (2.0 * f64::consts::PI * time as f64 / TIMES_PER_YEAR).sin().max(0.0);      // half sine wave period growth, uniform over terrain
-        //self.season = (time as f64 % TIMES_PER_YEAR) / TIMES_PER_YEAR;
-        self.season = (2.0 * f64::consts::PI * (time as f64 % TIMES_PER_YEAR) / TIMES_PER_YEAR).sin();
+        self.climate.update(time);
 
-        //// Grow new food over time
-        if time % 10 == 0 {
-            for row in 0..self.size[1] {
-                //let season = (f64::consts::PI * (row as f64 / self.size[1] as f64 + self.season / TIMES_PER_YEAR)).sin();           // permanently biases top portion of screen
-                let season = (2.0 * f64::consts::PI * ((row as f64 / self.size[1] as f64 + self.season) % 1.0) + (f64::consts::PI / 2.0)).sin().max(0.0);     // window of growth moving upward and wrapping
+        if let Some(mut scent) = self.scent.take() {
+            scent.timeslice(self);
+            self.scent = Some(scent);
+        }
 
+        //// Grow new food over time
+        if time % self.growth_interval == 0 {
+            if let Some(vegetation) = self.vegetation {
+                vegetation.grow(self);
+            } else {
                 for col in 0..self.size[0] {
-                    self.tiles[col][row].grow(season);
-                }
-
-                if season >= 0.99 {
-                    self.season_height = row;
+                    for row in 0..self.size[1] {
+                        let growth = self.climate.growth(col, row);
+                        self.tiles[col][row].grow(growth, &self.resources);
+                    }
                 }
             }
         }
     }
 }
 
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum TileKind {
+    Ground,
+    /// Blocks movement and never grows food
+    Wall,
+    /// Slows down creatures moving through it
+    Water,
+    /// Hurts creatures standing on it
+    Hazard,
+}
+
+impl TileKind {
+    /// The fraction of a creature's speed that it keeps while moving through the tile
+    pub fn speed(&self) -> f64
+    {
+        match *self {
+            TileKind::Water => 0.3,
+            _ => 1.0,
+        }
+    }
+
+    /// The value seen by a creature's terrain sensors
+    pub fn sense(&self) -> f64
+    {
+        match *self {
+            TileKind::Ground => 0.0,
+            TileKind::Water => 0.33,
+            TileKind::Hazard => 0.67,
+            TileKind::Wall => 1.0,
+        }
+    }
+}
+
+/// The size lost by a creature for each tick it spends on a hazard, or the energy lost if it has a metabolism
+const HAZARD_DAMAGE : f64 = 0.01;
+const HAZARD_DRAIN : f64 = 1.0;
+
+/// The number of bites a creature takes when it spends the whole tick eating, rather than eating as it moves
+const EATING_BITES : usize = 3;
+
+/// The fraction of the most food a tile can hold, above which it counts as having plenty of food
+const RICH_FOOD : f64 = 0.5;
+
+/// The smallest size a creature can be without dying
+const MIN_SIZE : f64 = 0.25;
+
+/// How close another creature has to be to attack it, how much size is taken from it, and the fraction of that the attacker
+/// gets to keep
+const ATTACK_RANGE : f64 = 1.5;
+const ATTACK_DAMAGE : f64 = 0.1;
+const ATTACK_GAIN : f64 = 0.5;
+
+/// The most scent a creature can leave on its tile each tick, in each channel
+const SCENT_DEPOSIT : f64 = 0.5;
+
 #[derive(Copy, Clone)]
 pub struct Tile {
     pub ttype: i32,
-    pub food: f64,
+    pub food: Amounts,
+    pub kind: TileKind,
+    /// The number of growth steps left before each resource regrows, after dying off from overgrazing
+    pub fallow: [u16; MAX_RESOURCES],
 }
 
 impl Tile {
-    fn new(ttype: i32) -> Tile
+    pub fn new(ttype: i32, resources: &Vec<Resource>) -> Tile
     {
+        let mut food = [0.0; MAX_RESOURCES];
+        for (i, resource) in resources.iter().enumerate() {
+            food[i] = rand::thread_rng().gen_range(0.0, resource.max.max(f64::EPSILON));
+        }
+
         return Tile {
             ttype: ttype,
-            food: rand::thread_rng().gen_range(0.0, 100.0),
+            food: food,
+            kind: TileKind::Ground,
+            fallow: [0; MAX_RESOURCES],
         };
     }
 
-    fn feed(&mut self) -> f64
+    pub fn total_food(&self) -> f64
     {
-        //let r = self.food.min(rand::thread_rng().gen_range(0.1, 1.0));
-        //let r = self.food.min(rand::thread_rng().gen_range(0.1, 1.0) * (self.food / 50.0).sqrt());
-        let r = self.food.min(self.food.sqrt() * 0.1 * rand::thread_rng().gen_range(0.8, 1.2));
-        self.food -= r;
-        return r;
+        self.food.iter().sum()
     }
 
-    fn grow(&mut self, season: f64)
+    /// The amount of food on the tile that a creature with the given digestion can make use of
+    pub fn digestible_food(&self, digestion: &Amounts) -> f64
     {
-        //self.food += rand::thread_rng().gen_range(0.0, 1.0) * season;
-        self.food += rand::thread_rng().gen_range(0.0, 0.20) * season * (self.ttype as f64).powf(2.0);
-        self.food = self.food.min(100.0).max(0.0);
+        self.food.iter().zip(digestion.iter()).map(|(food, efficiency)| food * efficiency).sum()
     }
-}
 
+    /// Removes some of each resource, in proportion to how well the creature can digest it, and returns the amounts eaten
+    fn feed(&mut self, digestion: &Amounts) -> Amounts
+    {
+        let mut eaten = [0.0; MAX_RESOURCES];
+        for i in 0..MAX_RESOURCES {
+            if digestion[i] <= 0.0 {
+                continue;
+            }
+
+            //let r = self.food.min(rand::thread_rng().gen_range(0.1, 1.0));
+            //let r = self.food.min(rand::thread_rng().gen_range(0.1, 1.0) * (self.food / 50.0).sqrt());
+            let r = self.food[i].min(self.food[i].sqrt() * 0.1 * rand::thread_rng().gen_range(0.8, 1.2)) * digestion[i];
+            self.food[i] -= r;
+            eaten[i] = r;
+        }
+        return eaten;
+    }
+
+    fn grow(&mut self, season: f64, resources: &Vec<Resource>)
+    {
+        if self.kind == TileKind::Wall {
+            return;
+        }
+
+        for (i, resource) in resources.iter().enumerate() {
+            //self.food += rand::thread_rng().gen_range(0.0, 1.0) * season;
+            self.food[i] += resource.growth(self.ttype, MAX_TTYPE, season);
+            self.food[i] = self.food[i].min(resource.max).max(0.0);
+        }
+    }
+}
 
 
-const CREAT_INIT : i32 = 100;
 
 pub type CreatureID = i32;
 
+#[derive(Serialize, Deserialize)]
 pub struct Creature {
     pub id: CreatureID,
     pub parent: CreatureID,
@@ -245,12 +678,54 @@ pub struct Creature {
     pub size: f64,
     pub speed: f64,
     pub angle: f64,
+
+    // fields added since the first version go at the end, so that older files in MessagePack, which stores structs as
+    // lists, can still be read with the defaults filled in
+    #[serde(default = "default_digestion")]
+    pub digestion: Amounts,
+    #[serde(default = "default_metabolism")]
+    pub metabolism: Metabolism,
+    /// The signal the creature is currently making, for other creatures nearby to hear
+    #[serde(default)]
+    pub signal: Signal,
+    /// An inherited multiplier on the lifespan, which is only used if aging is heritable
+    #[serde(default = "default_longevity")]
+    pub longevity: f64,
+    /// The inherited fraction of the creature's size and energy that it gives to each child, if the brain decides when to
+    /// reproduce
+    #[serde(default = "default_investment")]
+    pub investment: f64,
+    #[serde(default)]
+    pub behaviour: Behaviour,
+    /// How different the creature's behaviour was from the others when it was last measured
+    #[serde(default)]
+    pub novelty: f64,
 }
 
 static mut last_id : CreatureID = 0;
 
+fn default_digestion() -> Amounts
+{
+    resource::random_digestion(1)
+}
+
+fn default_metabolism() -> Metabolism
+{
+    Metabolism::new(1)
+}
+
+fn default_longevity() -> f64
+{
+    1.0
+}
+
+fn default_investment() -> f64
+{
+    0.5
+}
+
 impl Creature {
-    fn new(x : f64, y: f64, size: f64, speed: f64, angle: f64, birthday: WorldTime, colour: f32, parent: CreatureID, ancestor: CreatureID, brain: Option<Brain>) -> Creature
+    fn new(x : f64, y: f64, size: f64, speed: f64, angle: f64, birthday: WorldTime, colour: f32, parent: CreatureID, ancestor: CreatureID, brain: Option<Brain>, digestion: Amounts, metabolism: Metabolism, longevity: f64, investment: f64) -> Creature
     {
         let id = unsafe {
             last_id += 1;
@@ -258,7 +733,7 @@ impl Creature {
         };
 
         let newbrain = match brain {
-            Some(brain) => brain,
+            Some(mut brain) => { brain.fit(); brain },
             None => Brain::new()
         };
 
@@ -273,6 +748,13 @@ impl Creature {
             spawns: 0,
             eaten: 0.0,
             brain: newbrain,
+            digestion: digestion,
+            metabolism: metabolism,
+            signal: [0.0; SIGNAL_CHANNELS],
+            longevity: longevity,
+            investment: investment,
+            behaviour: Behaviour::default(),
+            novelty: 0.0,
 
             position: [ x, y ],
             size: size,
@@ -281,26 +763,36 @@ impl Creature {
         }
     }
 
-    fn generate(size: [usize; 2], birthday: WorldTime, colour: f32, ancestor: CreatureID, brain: Option<Brain>) -> Creature
+    pub fn generate(terrain: &Terrain, birthday: WorldTime, colour: f32, ancestor: CreatureID, brain: Option<Brain>) -> Creature
     {
-        let position = ( rand::thread_rng().gen_range(0.0, size[0] as f64), rand::thread_rng().gen_range(0.0, size[1] as f64) );
+        let position = terrain.random_position();
         let size = rand::thread_rng().gen_range(0.75, 1.25);
 
-        return Creature::new(position.0, position.1, size, 0.05, 0.0, birthday, colour, -1, ancestor, brain);
+        return Creature::new(position[0], position[1], size, 0.05, 0.0, birthday, colour, -1, ancestor, brain, resource::random_digestion(terrain.resources.len()), Metabolism::new(terrain.resources.len()), default_longevity(), default_investment());
     }
 
-    fn spawn(&mut self, birthday: WorldTime) -> Creature
+    /// Buds a new creature, which is given the invested fraction of the parent's size and energy
+    fn spawn(&mut self, birthday: WorldTime, resources: usize, investment: f64) -> Creature
     {
         self.spawns += 1;
 
         let newcolour = self.colour + rand::thread_rng().gen_range(-0.1 as f32, 0.1 as f32).min(1.0).max(0.0);
         //let size = self.size + rand::thread_rng().gen_range(-0.25, 0.25);
-        let size = self.size / 2.0;
+        let size = self.size * investment;
         self.size -= size;
-        return Creature::new(self.position[0] + 2.0, self.position[1] + 2.0, size, self.speed, self.angle, birthday, newcolour, self.id, self.ancestor, Some(self.brain.spawn()));
+
+        let mut metabolism = self.metabolism.spawn(resources);
+        metabolism.energy = self.metabolism.energy * investment;
+        self.metabolism.energy -= metabolism.energy;
+
+        let newinvestment = (self.investment + rand::thread_rng().gen_range(-0.02, 0.02)).max(0.1).min(0.9);
+
+        return Creature::new(self.position[0] + 2.0, self.position[1] + 2.0, size, self.speed, self.angle, birthday, newcolour, self.id, self.ancestor, Some(self.brain.spawn()), resource::mutate_digestion(&self.digestion, resources), metabolism, aging::mutate_longevity(self.longevity), newinvestment);
     }
 
-    fn timeslice(&mut self, terrain : &mut Terrain)
+    /// Senses, moves, and eats for one tick.  Returns the action that needs the rest of the world to carry it out, which is
+    /// reproducing or attacking, if the brain chose one
+    fn timeslice(&mut self, terrain : &mut Terrain, rules: &Rules, heard: Signal, time: WorldTime) -> Option<Action>
     {
         //self.x += rand::thread_rng().gen_range(-0.05, 0.05);
         //self.y += rand::thread_rng().gen_range(-0.05, 0.05);
@@ -308,28 +800,77 @@ impl Creature {
         //self.angle += rand::thread_rng().gen_range(-0.1, 0.1);
         //self.speed += rand::thread_rng().gen_range(-0.001, 0.001);
 
-        let foodbelow = terrain.tiles[self.position[0] as usize][self.position[1] as usize].food;
-        let infront = terrain.wrap_position([ self.position[0] + 1.0 * self.angle.cos(), self.position[1] + 1.0 * self.angle.sin() ]);
-        let foodahead = terrain.tiles[infront[0] as usize][infront[1] as usize].food;
-        let leftfront = terrain.wrap_position([ self.position[0] + 1.0 * (self.angle + f64::consts::PI / 4.0).cos(), self.position[1] + 1.0 * (self.angle + f64::consts::PI / 4.0).sin() ]);
-        let foodleft = terrain.tiles[leftfront[0] as usize][leftfront[1] as usize].food;
-        let rightfront = terrain.wrap_position([ self.position[0] + 1.0 * (self.angle - f64::consts::PI / 4.0).cos(), self.position[1] + 1.0 * (self.angle - f64::consts::PI / 4.0).sin() ]);
-        let foodright = terrain.tiles[rightfront[0] as usize][rightfront[1] as usize].food;
-
-        let input : Vec<f64> = vec!(foodbelow, foodahead, foodleft, foodright, self.size, self.angle, self.speed);
-        let output = match self.brain.forward(&input) {
+        let input = self.sense(terrain, heard);
+        let output = match self.brain.forward(&input, rules.plasticity) {
             Some(output) => output,
-            None => return,
+            None => return None,
         };
 
+        let eaten = self.eaten;
+        let action = rules.actions.map(|actions| actions.choose(&output[ACTION_OUTPUT..], rules.reproduction == Reproduction::Brain));
+        let result = self.act(terrain, rules, &output, action, time);
+        if rules.plasticity == Some(Plasticity::Reward) {
+            self.brain.reinforce(self.eaten - eaten);
+        }
+        return result;
+    }
+
+    /// Moves and eats for one tick as told by something outside the world, instead of by the brain
+    fn control(&mut self, terrain : &mut Terrain, rules: &Rules, control: &Control, time: WorldTime) -> Option<Action>
+    {
+        match *control {
+            Control::Outputs(ref outputs) => {
+                let mut output = outputs.clone();
+                output.resize(BRAIN_OUT as usize, 0.0);
+                let action = rules.actions.map(|actions| actions.choose(&output[ACTION_OUTPUT..], rules.reproduction == Reproduction::Brain));
+                self.act(terrain, rules, &output, action, time)
+            },
+            Control::Action(action) => self.act(terrain, rules, &vec![0.0; BRAIN_OUT as usize], Some(action), time),
+        }
+    }
+
+    /// Returns the inputs to the brain: the food below and ahead, the creature's own state, the climate, the kinds of tiles
+    /// ahead, the scents ahead, and the signals heard
+    pub fn sense(&self, terrain: &Terrain, heard: Signal) -> Vec<f64>
+    {
+        let foodbelow = terrain.tiles[self.position[0] as usize][self.position[1] as usize].digestible_food(&self.digestion);
+        let (foodahead, kindahead, scentahead) = self.look(terrain, self.angle);
+        let (foodleft, kindleft, scentleft) = self.look(terrain, self.angle + f64::consts::PI / 4.0);
+        let (foodright, kindright, scentright) = self.look(terrain, self.angle - f64::consts::PI / 4.0);
+
+        let climate = terrain.climate.growth(self.position[0] as usize, self.position[1] as usize);
+
+        let mut input : Vec<f64> = vec!(foodbelow, foodahead, foodleft, foodright, self.size, self.angle, self.speed, climate, kindahead, kindleft, kindright);
+        for scent in &[scentahead, scentleft, scentright] {
+            input.extend_from_slice(scent);
+        }
+        input.extend_from_slice(&heard);
+        return input;
+    }
+
+    /// Carries out the outputs of the brain, or the chosen action if there is one
+    fn act(&mut self, terrain : &mut Terrain, rules: &Rules, output: &Vec<f64>, action: Option<Action>, time: WorldTime) -> Option<Action>
+    {
+        let foodbelow = terrain.tiles[self.position[0] as usize][self.position[1] as usize].digestible_food(&self.digestion);
+
         //// Continous Control
         //self.angle += (output[0] - 0.5) * 0.1;
         //self.speed += (output[1] - 0.5) * 0.001;
 
-        //// Stepped Control
-        self.angle += if output[0] > 0.5 { 0.2 } else if output[1] > 0.5 { -0.2 } else { 0.0 };
-        self.speed = if output[2] > 0.5 { 0.2 } else { 0.001 };
-        //self.speed = if output[2] > 0.9 { 0.3 } else if output[2] > 0.6 { 0.15 } else { 0.001 };
+        let heading = self.angle;
+        match action {
+            //// Discrete Actions
+            Some(action) => {
+                self.angle += match action { Action::TurnLeft => 0.2, Action::TurnRight => -0.2, _ => 0.0 };
+                self.speed = if action == Action::Move { 0.2 } else { 0.0 };
+            },
+            //// Stepped Control
+            None => {
+                self.angle += if output[0] > 0.5 { 0.2 } else if output[1] > 0.5 { -0.2 } else { 0.0 };
+                self.speed = if output[2] > 0.5 { 0.2 } else { 0.001 };
+                //self.speed = if output[2] > 0.9 { 0.3 } else if output[2] > 0.6 { 0.15 } else { 0.001 };
+            },
+        }
 
         /*
         if self.angle < 0.0 {
@@ -339,62 +880,128 @@ impl Creature {
         */
         self.speed = self.speed.max(0.0).min(1.0);
 
-        self.position[0] += self.speed * self.angle.cos();
-        self.position[1] += self.speed * self.angle.sin();
-        self.position = terrain.wrap_position(self.position);
+        let distance = self.speed * terrain.tile_at(self.position).kind.speed();
+        let target = [ self.position[0] + distance * self.angle.cos(), self.position[1] + distance * self.angle.sin() ];
+        match terrain.wrap_position(target) {
+            Some(newpos) => if !terrain.is_blocked(newpos) {
+                self.position = newpos;
+            },
+            None => match terrain.boundary {
+                Boundary::Bounce => {
+                    // reflect off whichever edges were crossed
+                    if target[0] < 0.0 || target[0] >= terrain.size[0] as f64 {
+                        self.angle = f64::consts::PI - self.angle;
+                    }
+                    if target[1] < 0.0 || target[1] >= terrain.size[1] as f64 {
+                        self.angle = -self.angle;
+                    }
+                },
+                Boundary::Lethal => {
+                    self.size = 0.0;
+                    return None;
+                },
+                _ => { },
+            },
+        }
 
-        let food = terrain.tiles[self.position[0] as usize][self.position[1] as usize].feed();
-        self.eaten += food;
-        self.size -= self.size * 0.005;  // cost to live
-        self.size += ((1.0 / self.size).powf(2.0) * food * 0.01) - 0.005;
-        //self.size += ((1.0 / self.size) * food * 0.01);
-    }
+        self.behaviour.record(self.position, terrain.size, distance, heading - self.angle, foodbelow >= terrain.rich_food);
 
-    pub fn write(&self, filename: &str) -> Result<(), io::Error>
-    {
-        let mut f = match File::create(filename) {
-            Ok(f) => f,
-            Err(err) => return Err(err),
-        };
+        if rules.signals {
+            for channel in 0..SIGNAL_CHANNELS {
+                self.signal[channel] = output[3 + SCENT_CHANNELS + channel];
+            }
+        }
+
+        if let Some(ref mut scent) = terrain.scent {
+            for channel in 0..SCENT_CHANNELS {
+                scent.deposit(self.position, channel, output[3 + channel].max(0.0) * SCENT_DEPOSIT);
+            }
+        }
+
+        if terrain.tile_at(self.position).kind == TileKind::Hazard {
+            if rules.metabolism {
+                self.metabolism.energy -= HAZARD_DRAIN;
+            } else {
+                self.size -= HAZARD_DAMAGE;
+            }
+        }
 
-        let encoded = rustc_serialize::json::as_pretty_json(&self.brain);
-        match f.write(format!("{}", encoded).as_bytes()) {
-            Ok(_) => return Ok(()),
-            Err(err) => return Err(err),
+        let bites = match action {
+            None => 1,
+            Some(Action::Eat) => EATING_BITES,
+            Some(_) => 0,
         };
-    }
+        let mut eaten = [0.0; MAX_RESOURCES];
+        for _ in 0..bites {
+            let bite = terrain.tiles[self.position[0] as usize][self.position[1] as usize].feed(&self.digestion);
+            for i in 0..MAX_RESOURCES {
+                eaten[i] += bite[i];
+            }
+        }
+        let food : f64 = eaten.iter().sum();
+        self.eaten += food;
 
-    pub fn read(filename: &str, size: [usize; 2], birthday: WorldTime, ancestor: CreatureID) -> Result<Creature, io::Error>
-    {
-        let mut f = match File::open(filename) {
-            Ok(f) => f,
-            Err(err) => return Err(err),
+        let age = time - self.birthday;
+        let senescence = rules.aging.map_or(0.0, |aging| aging.senescence(age, self.longevity));
+        let cost = match (rules.actions, action) {
+            (Some(actions), Some(action)) => actions.cost(action),
+            _ => 0.0,
         };
+        let effort = (1.0 + senescence + cost).max(0.0);
+
+        if rules.metabolism {
+            self.size += self.metabolism.timeslice(&eaten, self.size, self.speed, effort);
+        } else {
+            self.size -= self.size * 0.005 * effort;  // cost to live
+            self.size += ((1.0 / self.size).powf(2.0) * food * 0.01) - 0.005;
+            //self.size += ((1.0 / self.size) * food * 0.01);
+        }
 
-        let mut buffer = String::new();
-        match f.read_to_string(&mut buffer) {
-            Ok(_) => (),
-            Err(err) => return Err(err),
+        if let Some(aging) = rules.aging {
+            if rand::thread_rng().gen_range(0.0, 1.0) < aging.mortality(age, self.longevity) {
+                self.size = 0.0;
+            }
         }
 
-        let brain = match rustc_serialize::json::decode(buffer.as_str()) {
-            Ok(brain) => brain,
-            Err(err) => { println!("{}", err); return Err(io::Error::new(ErrorKind::InvalidData, "error decoding json")); },
-        };
+        match action {
+            Some(Action::Reproduce) | Some(Action::Attack) => action,
+            Some(_) => None,
+            None => if output[REPRODUCE_OUTPUT] > 0.5 { Some(Action::Reproduce) } else { None },
+        }
+    }
 
-        return Ok(Creature::generate(size, birthday, rand::thread_rng().gen_range(0.0 as f32, 1.0 as f32).min(1.0).max(0.0), ancestor, brain));
+    /// Returns the food, the kind of tile, and the scent one step away in the given direction.  Past an edge that doesn't
+    /// wrap, there's no food or scent, and the edge looks like a wall or a hazard
+    fn look(&self, terrain: &Terrain, angle: f64) -> (f64, f64, scent::Levels)
+    {
+        match terrain.wrap_position([ self.position[0] + angle.cos(), self.position[1] + angle.sin() ]) {
+            Some(position) => {
+                let tile = terrain.tile_at(position);
+                let scent = terrain.scent.as_ref().map_or([0.0; SCENT_CHANNELS], |scent| scent.level(position));
+                (tile.digestible_food(&self.digestion), tile.kind.sense(), scent)
+            },
+            None => (0.0, terrain.boundary.sense(), [0.0; SCENT_CHANNELS]),
+        }
     }
 }
 
 
-const BRAIN_IN : u32 = 7;
+pub const BRAIN_IN : u32 = 11 + 3 * SCENT_CHANNELS as u32 + SIGNAL_CHANNELS as u32;
 const BRAIN_L1 : u32 = 10;
 const BRAIN_L2 : u32 = 10;
-const BRAIN_OUT : u32 = 3;
+pub const BRAIN_OUT : u32 = 4 + SCENT_CHANNELS as u32 + SIGNAL_CHANNELS as u32 + ACTIONS as u32;
+
+/// The output that decides whether to reproduce, which comes after the movement, scent and signal outputs
+const REPRODUCE_OUTPUT : usize = 3 + SCENT_CHANNELS + SIGNAL_CHANNELS;
+/// The first of the outputs for each action, which are only used when the creatures choose one action at a time
+const ACTION_OUTPUT : usize = REPRODUCE_OUTPUT + 1;
 
-#[derive(RustcDecodable, RustcEncodable)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Brain {
     layers: Vec<AnyLayer>,
+    /// The food the creature expects to eat on each tick, which is learned during its life when learning is rewarded
+    #[serde(default)]
+    baseline: f64,
 }
 
 impl Brain {
@@ -407,10 +1014,26 @@ impl Brain {
 
         Brain {
             layers: layers,
+            baseline: 0.0,
         }
     }
 
-    fn spawn(&self) -> Brain
+    /// Resizes the first and last layers to the current number of inputs and outputs, so that brains saved before new
+    /// senses or actions were added can still be used.  New inputs start out ignored, and new outputs start out random
+    fn fit(&mut self)
+    {
+        if let Some(first) = self.layers.first_mut() {
+            first.fit_inputs(BRAIN_IN);
+        }
+        if let Some(last) = self.layers.last_mut() {
+            last.fit_outputs(BRAIN_OUT);
+        }
+        for layer in &mut self.layers {
+            layer.fit_rates();
+        }
+    }
+
+    pub fn spawn(&self) -> Brain
     {
         let mut layers : Vec<AnyLayer> = vec!();
         for layer in &self.layers {
@@ -419,14 +1042,15 @@ impl Brain {
 
         Brain {
             layers: layers,
+            baseline: 0.0,
         }
     }
 
-    fn forward(&mut self, input : &Vec<f64>) -> Option<Vec<f64>>
+    fn forward(&mut self, input : &Vec<f64>, plasticity: Option<Plasticity>) -> Option<Vec<f64>>
     {
         let mut output = input.to_vec();
         for layer in &mut self.layers {
-            match layer.forward(&output) {
+            match layer.forward(&output, plasticity) {
                 Some(result) => output = result,
                 None => {
                     println!("mismatched matrix multiplication");
@@ -436,10 +1060,32 @@ impl Brain {
         }
         return Some(output);
     }
+
+    /// Strengthens the connections that were active on the last tick if the creature ate more than it expected to, and
+    /// weakens them if it ate less
+    fn reinforce(&mut self, eaten: f64)
+    {
+        let reward = eaten - self.baseline;
+        self.baseline += (eaten - self.baseline) * plasticity::BASELINE_RATE;
+        for layer in &mut self.layers {
+            layer.reinforce(reward);
+        }
+    }
+
+    /// Returns the weights and biases of each layer, flattened into one list per layer
+    pub fn layer_params(&self) -> Vec<Vec<f64>>
+    {
+        self.layers.iter().map(|layer| layer.params()).collect()
+    }
+
+    pub fn params(&self) -> Vec<f64>
+    {
+        self.layer_params().concat()
+    }
 }
 
 
-#[derive(RustcDecodable, RustcEncodable)]
+#[derive(Clone, Serialize, Deserialize)]
 enum AnyLayer {
     FC(FCLayer),
 }
@@ -452,16 +1098,51 @@ impl AnyLayer {
         }
     }
 
-    fn forward(&mut self, x : &Vec<f64>) -> Option<Vec<f64>>
+    fn forward(&mut self, x : &Vec<f64>, plasticity: Option<Plasticity>) -> Option<Vec<f64>>
     {
         match self {
-            &mut AnyLayer::FC(ref mut layer) => layer.forward(x),
+            &mut AnyLayer::FC(ref mut layer) => layer.forward(x, plasticity),
+        }
+    }
+
+    fn reinforce(&mut self, reward: f64)
+    {
+        match self {
+            &mut AnyLayer::FC(ref mut layer) => layer.reinforce(reward),
+        }
+    }
+
+    fn params(&self) -> Vec<f64>
+    {
+        match self {
+            &AnyLayer::FC(ref layer) => layer.params(),
+        }
+    }
+
+    fn fit_inputs(&mut self, width: u32)
+    {
+        match self {
+            &mut AnyLayer::FC(ref mut layer) => layer.fit_inputs(width),
+        }
+    }
+
+    fn fit_outputs(&mut self, height: u32)
+    {
+        match self {
+            &mut AnyLayer::FC(ref mut layer) => layer.fit_outputs(height),
+        }
+    }
+
+    fn fit_rates(&mut self)
+    {
+        match self {
+            &mut AnyLayer::FC(ref mut layer) => layer.fit_rates(),
         }
     }
 }
 
 
-#[derive(Copy, Clone, RustcDecodable, RustcEncodable)]
+#[derive(Copy, Clone, Serialize, Deserialize)]
 enum Activation {
     Sigmoid,
     Tanh,
@@ -486,11 +1167,23 @@ fn activation(formula: Activation, x: f64) -> f64
 const PARAM_BOUND : f64 = 3.0;
 
 #[allow(non_snake_case)]
-#[derive(RustcDecodable, RustcEncodable)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct FCLayer {
     W: Vec<Vec<f64>>,
     b: Vec<f64>,
     activation: Activation,
+    /// The inherited learning rate of the connections into each neuron, which is only used when brains learn
+    #[serde(default)]
+    rates: Vec<f64>,
+    /// The changes to the inherited weights learned during the creature's life, which aren't passed on to its children or
+    /// saved, and are only allocated once the brain starts learning
+    #[serde(skip)]
+    learned: Vec<Vec<f64>>,
+    /// The inputs and outputs on the last tick, which are remembered until the reward for them is known
+    #[serde(skip)]
+    last_input: Vec<f64>,
+    #[serde(skip)]
+    last_output: Vec<f64>,
 }
 
 #[allow(non_snake_case)]
@@ -516,6 +1209,10 @@ impl FCLayer {
             W: W,
             b: b,
             activation: activation,
+            rates: (0..height).map(|_| plasticity::random_rate()).collect(),
+            learned: vec!(),
+            last_input: vec!(),
+            last_output: vec!(),
         })
     }
 
@@ -539,11 +1236,22 @@ impl FCLayer {
             W: W,
             b: b,
             activation: self.activation,
+            rates: self.rates.iter().map(|&rate| plasticity::mutate_rate(rate)).collect(),
+            learned: vec!(),
+            last_input: vec!(),
+            last_output: vec!(),
         })
     }
 
-    fn forward(&mut self, x : &Vec<f64>) -> Option<Vec<f64>>
+    fn forward(&mut self, x : &Vec<f64>, plasticity: Option<Plasticity>) -> Option<Vec<f64>>
     {
+        // creatures loaded whole from a snapshot haven't been fitted, so the learning rates are checked here too
+        let learning = plasticity.is_some();
+        if learning {
+            self.fit_rates();
+            self.fit_learned();
+        }
+
         let mut output : Vec<f64> = vec!();
         for v in 0..self.W.len() {
             let Wv = &self.W[v];
@@ -553,14 +1261,101 @@ impl FCLayer {
             }
 
             let mut sum = 0.0;
-            for u in 0..Wv.len() {
-                sum += Wv[u] * x[u];
+            if learning {
+                let Lv = &self.learned[v];
+                for u in 0..Wv.len() {
+                    sum += (Wv[u] + Lv[u]) * x[u];
+                }
+            } else {
+                for u in 0..Wv.len() {
+                    sum += Wv[u] * x[u];
+                }
             }
 
             output.push(activation(self.activation, sum));
         }
+
+        match plasticity {
+            Some(Plasticity::Hebbian) => {
+                for v in 0..self.W.len() {
+                    for u in 0..x.len() {
+                        let weight = self.W[v][u] + self.learned[v][u];
+                        self.learn(v, u, plasticity::hebbian(self.rates[v], x[u], output[v], weight));
+                    }
+                }
+            },
+            Some(Plasticity::Reward) => {
+                self.last_input = x.clone();
+                self.last_output = output.clone();
+            },
+            None => { },
+        }
         return Some(output);
     }
+
+    fn reinforce(&mut self, reward: f64)
+    {
+        if self.last_output.len() != self.W.len() {
+            return;
+        }
+
+        for v in 0..self.W.len() {
+            for u in 0..self.last_input.len().min(self.W[v].len()) {
+                let change = plasticity::reinforce(self.rates[v], self.last_input[u], self.last_output[v], reward);
+                self.learn(v, u, change);
+            }
+        }
+    }
+
+    /// Changes a learned weight, keeping the total weight within the same bounds as the inherited weights
+    fn learn(&mut self, v: usize, u: usize, change: f64)
+    {
+        let weight = (self.W[v][u] + self.learned[v][u] + change).min(PARAM_BOUND).max(-PARAM_BOUND);
+        self.learned[v][u] = weight - self.W[v][u];
+    }
+
+    fn fit_inputs(&mut self, width: u32)
+    {
+        for Wv in &mut self.W {
+            Wv.resize(width as usize, 0.0);
+        }
+    }
+
+    fn fit_outputs(&mut self, height: u32)
+    {
+        let width = self.W.first().map_or(0, |Wv| Wv.len());
+        while self.W.len() < height as usize {
+            self.W.push((0..width).map(|_| rand::thread_rng().gen_range(-PARAM_BOUND, PARAM_BOUND)).collect());
+            self.b.push(rand::thread_rng().gen_range(-PARAM_BOUND, PARAM_BOUND));
+        }
+        self.W.truncate(height as usize);
+        self.b.truncate(height as usize);
+    }
+
+    /// Gives every neuron a learning rate
+    fn fit_rates(&mut self)
+    {
+        while self.rates.len() < self.W.len() {
+            self.rates.push(plasticity::random_rate());
+        }
+        self.rates.truncate(self.W.len());
+    }
+
+    /// Starts the learned changes at zero if nothing has been learned yet, or if they no longer match the weights
+    fn fit_learned(&mut self)
+    {
+        let fits = self.learned.len() == self.W.len() && self.learned.iter().zip(self.W.iter()).all(|(Lv, Wv)| Lv.len() == Wv.len());
+        if !fits {
+            self.learned = self.W.iter().map(|Wv| vec![0.0; Wv.len()]).collect();
+        }
+    }
+
+    fn params(&self) -> Vec<f64>
+    {
+        let mut params = self.W.concat();
+        params.extend_from_slice(&self.b);
+        return params;
+    }
 }
 
 
@@ -603,3 +1398,151 @@ impl fmt::Display for Layer {
 }
 */
 
+
+
+/// The tiles of a terrain are too big for the stack of a test thread, so tests that need one run on a bigger stack
+#[cfg(test)]
+pub fn with_terrain<F: FnOnce(Terrain) + Send + 'static>(config: TerrainConfig, test: F)
+{
+    use std::thread;
+    thread::Builder::new().stack_size(64 * 1024 * 1024).spawn(move || test(Terrain::empty(&config))).unwrap().join().unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+    use generator::TerrainConfig;
+    use super::*;
+
+    #[test]
+    fn lineage_forgets_extinct_lines()
+    {
+        with_terrain(TerrainConfig::new(), |terrain| {
+            let founder = Creature::generate(&terrain, 1, 0.5, -1, None);
+            let dead_end = Creature::generate(&terrain, 1, 0.5, -1, None);
+            let child = Creature::generate(&terrain, 1, 0.5, -1, None);
+            let grandchild = Creature::generate(&terrain, 1, 0.5, -1, None);
+
+            let mut lineage = Lineage::new();
+            lineage.record_birth(dead_end.id, founder.id);
+            lineage.record_birth(child.id, founder.id);
+            lineage.record_birth(grandchild.id, child.id);
+            assert_eq!(lineage.descendants(founder.id), 3);
+            assert_eq!(lineage.descendants(child.id), 1);
+
+            // only the grandchild is still alive, so the dead end is forgotten, but its line back to the founder is kept
+            lineage.prune(&vec!(grandchild));
+            assert_eq!(lineage.parent(dead_end.id), None);
+            assert_eq!(lineage.parent(child.id), Some(founder.id));
+            assert_eq!(lineage.descendants(founder.id), 0);
+            assert_eq!(lineage.parents.len(), 2);
+        });
+    }
+
+    #[test]
+    fn founders_are_forgotten_when_their_lineage_dies_out()
+    {
+        with_terrain(TerrainConfig::new(), |terrain| {
+            let creatures = (0..2).map(|_| Creature::generate(&terrain, 1, 0.5, -1, None)).collect();
+            let mut world = World::from_creatures(creatures, terrain, Rules::new());
+            let immigrant = Creature::generate(&world.terrain, 1, 0.5, -1, None);
+            world.arrive(immigrant);
+            assert_eq!(world.founders.len(), 3);
+
+            // the first lineage dies out just before the records are pruned
+            let extinct = world.creatures[0].ancestor;
+            world.creatures[0].size = 0.0;
+            world.time = LINEAGE_INTERVAL - 1;
+            world.timeslice();
+
+            assert!(!world.founders.contains_key(&extinct));
+            assert_eq!(world.founders.len(), 2);
+        });
+    }
+
+    fn config_with(boundary: Boundary) -> TerrainConfig
+    {
+        let mut config = TerrainConfig::new();
+        config.boundary = boundary;
+        return config;
+    }
+
+    #[test]
+    fn torus_wraps_positions()
+    {
+        with_terrain(config_with(Boundary::Torus), |terrain| {
+            let (width, height) = (terrain.size[0] as f64, terrain.size[1] as f64);
+            assert_eq!(terrain.wrap_position([ -1.0, 5.0 ]), Some([ width - 1.0, 5.0 ]));
+            assert_eq!(terrain.wrap_position([ width, height + 2.5 ]), Some([ 0.0, 2.5 ]));
+            assert_eq!(terrain.wrap_position([ -3.0 * width - 0.5, 0.0 ]), Some([ width - 0.5, 0.0 ]));
+
+            // a tiny negative number rounds up to the size, which has to wrap back to the start
+            let wrapped = terrain.wrap_position([ -1e-20, -1e-20 ]).unwrap();
+            assert!(wrapped[0] >= 0.0 && wrapped[0] < width && wrapped[1] >= 0.0 && wrapped[1] < height);
+        });
+    }
+
+    #[test]
+    fn walls_stop_positions_at_the_edges()
+    {
+        with_terrain(config_with(Boundary::Wall), |terrain| {
+            let width = terrain.size[0] as f64;
+            assert_eq!(terrain.wrap_position([ 0.0, 0.0 ]), Some([ 0.0, 0.0 ]));
+            assert_eq!(terrain.wrap_position([ width - 0.5, 1.0 ]), Some([ width - 0.5, 1.0 ]));
+            assert_eq!(terrain.wrap_position([ -0.1, 1.0 ]), None);
+            assert_eq!(terrain.wrap_position([ width, 1.0 ]), None);
+        });
+    }
+
+    #[test]
+    fn distance_crosses_the_edges_only_on_a_torus()
+    {
+        with_terrain(config_with(Boundary::Torus), |terrain| {
+            let width = terrain.size[0] as f64;
+            assert_eq!(terrain.distance([ 0.5, 0.0 ], [ width - 0.5, 0.0 ]), 1.0);
+            assert_eq!(terrain.distance([ 0.0, 0.0 ], [ 3.0, 4.0 ]), 5.0);
+        });
+        with_terrain(config_with(Boundary::Wall), |terrain| {
+            let width = terrain.size[0] as f64;
+            assert_eq!(terrain.distance([ 0.5, 0.0 ], [ width - 0.5, 0.0 ]), width - 1.0);
+        });
+    }
+
+    #[test]
+    fn neighbours_at_the_corners()
+    {
+        with_terrain(config_with(Boundary::Torus), |terrain| {
+            let (width, height) = (terrain.size[0], terrain.size[1]);
+            assert_eq!(terrain.neighbours(0, 0), [ Some((width - 1, 0)), Some((1, 0)), Some((0, height - 1)), Some((0, 1)) ]);
+            assert_eq!(terrain.neighbours(width - 1, height - 1), [ Some((width - 2, height - 1)), Some((0, height - 1)), Some((width - 1, height - 2)), Some((width - 1, 0)) ]);
+        });
+        with_terrain(config_with(Boundary::Wall), |terrain| {
+            let (width, height) = (terrain.size[0], terrain.size[1]);
+            assert_eq!(terrain.neighbours(0, 0), [ None, Some((1, 0)), None, Some((0, 1)) ]);
+            assert_eq!(terrain.neighbours(width - 1, height - 1), [ Some((width - 2, height - 1)), None, Some((width - 1, height - 2)), None ]);
+        });
+    }
+
+    fn learned(brain: &Brain) -> usize
+    {
+        brain.layers.iter().map(|layer| match *layer { AnyLayer::FC(ref layer) => layer.learned.len() }).sum()
+    }
+
+    #[test]
+    fn brains_only_learn_with_plasticity()
+    {
+        let input = vec![0.5; BRAIN_IN as usize];
+        let mut brain = Brain::new();
+        brain.forward(&input, None).unwrap();
+        assert_eq!(learned(&brain), 0);
+
+        brain.forward(&input, Some(Plasticity::Hebbian)).unwrap();
+        assert!(learned(&brain) > 0);
+
+        // what's learned isn't saved
+        let saved = serde_json::to_string(&brain).unwrap();
+        assert!(!saved.contains("learned"));
+        let loaded : Brain = serde_json::from_str(&saved).unwrap();
+        assert_eq!(learned(&loaded), 0);
+    }
+}